@@ -0,0 +1,75 @@
+use std::collections::{BTreeSet, HashSet};
+
+use super::production::Production;
+
+// What can be decided about a grammar as written, as returned by `Cfg::analyze`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Analysis {
+    // Variables that derive at least one string of terminals, possibly the empty one
+    pub productive: BTreeSet<String>,
+    // Variables the starting variable can lead to
+    pub reachable: BTreeSet<String>,
+    // True if the starting variable derives no strings at all
+    pub is_empty: bool,
+    // Every string of the language in shortlex order, if there are finitely many.
+    // The empty string is included when the starting variable can derive it
+    pub finite_language: Option<Vec<Vec<String>>>,
+}
+
+#[allow(dead_code)]
+impl Analysis {
+    pub fn is_finite(&self) -> bool {
+        self.finite_language.is_some()
+    }
+}
+
+// Variables with a production made only of terminals and productive variables.
+// Symbols without productions of their own are terminals
+pub fn productive(productions: &[Production]) -> HashSet<String> {
+    let variables: HashSet<&str> = productions.iter().map(|p| p.symbol.as_str()).collect();
+    let mut productive: HashSet<String> = HashSet::new();
+
+    loop {
+        let before = productive.len();
+        for prod in productions.iter() {
+            if prod
+                .value
+                .iter()
+                .all(|s| !variables.contains(s.as_str()) || productive.contains(s))
+            {
+                productive.insert(prod.symbol.clone());
+            }
+        }
+
+        // Stop once nothing new was found
+        if productive.len() == before {
+            break;
+        }
+    }
+
+    productive
+}
+
+// Variables that appear in a derivation from the starting variable, itself included
+pub fn reachable(productions: &[Production], starting_variable: &str) -> HashSet<String> {
+    let variables: HashSet<&str> = productions.iter().map(|p| p.symbol.as_str()).collect();
+    let mut reachable: HashSet<String> = HashSet::new();
+    let mut to_visit = vec![starting_variable.to_string()];
+
+    while let Some(symbol) = to_visit.pop() {
+        if !reachable.insert(symbol.clone()) {
+            continue;
+        }
+
+        for prod in productions.iter().filter(|p| p.symbol == symbol) {
+            to_visit.extend(
+                prod.value
+                    .iter()
+                    .filter(|s| variables.contains(s.as_str()) && !reachable.contains(*s))
+                    .cloned(),
+            );
+        }
+    }
+
+    reachable
+}
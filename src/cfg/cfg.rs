@@ -1,7 +1,47 @@
-use rand::seq::SliceRandom;
-use std::collections::HashSet;
-
-use super::production::Production;
+use num_bigint::BigUint;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+
+use super::analysis::{productive, reachable, Analysis};
+use super::counting::DerivationCounter;
+use super::earley::Earley;
+use super::enumerate::Enumerate;
+use super::error::GrammarError;
+use super::generator::Generator;
+use super::incremental::IncrementalParser;
+use super::inside_outside::InsideOutside;
+use super::lint::{lint, Warning};
+use super::parse_tree::{epsilon_tree, ParseTree};
+use super::production::{epsilon_counts, epsilon_weights, nullable_witnesses, Origin, Production};
+use super::reader::read_grammar;
+use super::recognizer::Recognizer;
+use super::scanner::Scanner;
+use super::symbol_table::SymbolTable;
+use super::terminal_class::{matches_terminal, TerminalClass};
+use super::valiant::Valiant;
+use super::writer::write_grammar;
+
+// How many productions a sample is derived with at most, unless another budget is given
+const DEFAULT_MAX_SIZE: usize = 100;
+
+// For each cell of the CYK table, the partition and the rules of the left and right halves
+type BackPointers = Vec<Vec<Vec<Option<(usize, usize, usize)>>>>;
+
+// A chain of unit productions as ((from, to), (indices of the unit productions, weight))
+type UnitChain = ((String, String), (Vec<usize>, f64));
+
+// The algorithm used to test and parse strings
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    // CYK over the grammar converted to CNF
+    #[default]
+    Cyk,
+    // Earley over the grammar as written, skipping CNF conversion
+    Earley,
+}
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -9,15 +49,183 @@ pub struct Cfg {
     starting_variable: String,
     productions: Vec<Production>,
     triplets: Vec<(usize, usize, usize)>,
+    // The productions as they were written, before CNF conversion
+    original: Vec<Production>,
+    // The starting variable of `original`, CNF conversion may add a new one
+    original_start: String,
+    // Terminal classes like `[a-z]`, keyed by how they were written
+    classes: HashMap<String, TerminalClass>,
+    // Tokens declared with `NAME = /regex/`, as (name, pattern)
+    tokens: Vec<(String, String)>,
+    // The line each production of `original` was written on
+    lines: Vec<usize>,
+    // Terminals written in quotes
+    quoted: HashSet<String>,
+    // Every symbol of `productions`, including the ones CNF conversion made up
+    symbols: SymbolTable,
+    // Answers `test` for the CNF productions
+    recognizer: Recognizer,
+    backend: Backend,
+    // CNF conversion keeps every way of making a production, each with its own weight
+    // and origin, instead of the most probable one. Training sums over all of them
+    keep_derivations: bool,
 }
 
 #[allow(dead_code)]
 impl Cfg {
+    // Panics if the grammar cannot be read.
+    // Undefined and duplicate rules are allowed, see `try_new` for a strict version
     pub fn new(input: &str) -> Cfg {
-        let mut cfg = read_cfg(input);
-        cfg.convert_to_cnf();
-        cfg.create_triplets();
-        cfg
+        Cfg::with_backend(input, Backend::Cyk)
+    }
+
+    pub fn with_backend(input: &str, backend: Backend) -> Cfg {
+        match read_cfg(input, false) {
+            Ok(cfg) => cfg.prepare(backend),
+            Err(e) => panic!("Invalid grammar: {}", e),
+        }
+    }
+
+    pub fn try_new(input: &str) -> Result<Cfg, GrammarError> {
+        Cfg::try_with_backend(input, Backend::Cyk)
+    }
+
+    pub fn try_with_backend(input: &str, backend: Backend) -> Result<Cfg, GrammarError> {
+        Ok(read_cfg(input, true)?.prepare(backend))
+    }
+
+    fn prepare(mut self, backend: Backend) -> Cfg {
+        self.backend = backend;
+
+        self.symbols = SymbolTable::from_productions(&self.productions);
+
+        // Earley works on the grammar as written
+        if backend == Backend::Cyk {
+            self.convert_to_cnf();
+            self.create_triplets();
+            self.recognizer =
+                Recognizer::new(&self.productions, &self.classes, &self.starting_variable);
+        }
+
+        self
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    // The grammar as written, with the weights it has now, prepared again for the backend
+    fn rebuild(&self, backend: Backend) -> Cfg {
+        let productions = self
+            .original
+            .iter()
+            .enumerate()
+            .map(|(index, p)| Production {
+                origin: Some(Origin {
+                    index,
+                    ..Origin::default()
+                }),
+                ..p.clone()
+            })
+            .collect();
+
+        Cfg {
+            starting_variable: self.original_start.clone(),
+            productions,
+            triplets: vec![],
+            ..self.clone()
+        }
+        .prepare(backend)
+    }
+
+    // The grammar as written, in the format `Cfg::new` reads, with the current weights
+    pub fn to_grammar_string(&self) -> String {
+        write_grammar(
+            &self.original_start,
+            &self.original,
+            &self.classes,
+            &self.tokens,
+        )
+    }
+
+    // Re-estimates the weights of the written productions from a corpus with inside-outside.
+    // Stops once the log-likelihood of the corpus improves by less than `tolerance`,
+    // or after `max_iterations`. Sentences the grammar cannot derive are skipped.
+    // Returns the log-likelihood before each re-estimation
+    pub fn train(
+        &mut self,
+        corpus: &[Vec<&str>],
+        max_iterations: usize,
+        tolerance: f64,
+    ) -> Vec<f64> {
+        let mut log_likelihoods: Vec<f64> = Vec::new();
+
+        for _ in 0..max_iterations {
+            // Every derivation of the written grammar is kept, so the chart sums over them all
+            let cnf = Cfg {
+                keep_derivations: true,
+                ..self.clone()
+            }
+            .rebuild(Backend::Cyk);
+
+            // The written productions standing in for each nullable variable left out
+            let epsilon = epsilon_counts(&self.original);
+            let chart = InsideOutside {
+                productions: &cnf.productions,
+                triplets: &cnf.triplets,
+                classes: &cnf.classes,
+                starting_variable: &cnf.starting_variable,
+            };
+
+            // Expected uses of each written production over the whole corpus
+            let mut counts = vec![0.0; self.original.len()];
+            let mut log_likelihood = 0.0;
+            for sentence in corpus.iter() {
+                let Some((expected, log_prob)) = chart.expected_counts(sentence) else {
+                    continue;
+                };
+                log_likelihood += log_prob;
+
+                // A CNF production stands for every written production folded into it
+                for (prod, count) in cnf.productions.iter().zip(expected) {
+                    let Some(origin) = &prod.origin else {
+                        continue;
+                    };
+
+                    counts[origin.index] += count;
+                    origin.units.iter().for_each(|unit| counts[*unit] += count);
+                    for symbol in origin.removed.iter() {
+                        for (total, uses) in counts.iter_mut().zip(epsilon[symbol].iter()) {
+                            *total += count * uses;
+                        }
+                    }
+                }
+            }
+
+            // Each variable's productions get their share of its uses.
+            // Variables never used keep their weights
+            let mut totals: HashMap<String, f64> = HashMap::new();
+            for (prod, count) in self.original.iter().zip(counts.iter()) {
+                *totals.entry(prod.symbol.clone()).or_default() += count;
+            }
+            for (prod, count) in self.original.iter_mut().zip(counts) {
+                let total = totals[&prod.symbol];
+                if total > 0.0 {
+                    prod.weight = count / total;
+                }
+            }
+
+            let converged = log_likelihoods
+                .last()
+                .is_some_and(|last| log_likelihood - last < tolerance);
+            log_likelihoods.push(log_likelihood);
+            if converged {
+                break;
+            }
+        }
+
+        *self = self.rebuild(self.backend);
+        log_likelihoods
     }
 
     fn is_variable(&self, name: &str) -> bool {
@@ -33,23 +241,239 @@ impl Cfg {
         }
     }
 
-    // Tests if the string exists using the CYK algorithm
+    // Tests if the string exists using the chosen backend
     pub fn test(&self, input: Vec<&str>) -> bool {
         let n = input.len();
         if n == 0 {
             return false;
         }
 
+        if self.backend == Backend::Earley {
+            return Earley::recognize(
+                &self.productions,
+                &self.starting_variable,
+                &self.classes,
+                &input,
+            );
+        }
+
+        self.recognizer.recognize(&input)
+    }
+
+    // Whether the language has the empty string, which `test` always rejects
+    pub fn accepts_empty(&self) -> bool {
+        nullable_witnesses(&self.original).contains_key(&self.original_start)
+    }
+
+    // Like `test`, filling in the cells of each span length on up to `threads` threads.
+    // Only worth it for long inputs
+    pub fn test_parallel(&self, input: Vec<&str>, threads: usize) -> bool {
+        if self.backend == Backend::Earley {
+            return self.test(input);
+        }
+
+        self.recognizer.recognize_with_threads(&input, threads)
+    }
+
+    // Like `test`, with Valiant's reduction to matrix multiplication, see `Valiant`.
+    // Gives the same answers, and is faster on long inputs
+    pub fn test_valiant(&self, input: Vec<&str>) -> bool {
+        if self.backend == Backend::Earley {
+            return self.rebuild(Backend::Cyk).test_valiant(input);
+        }
+
+        Valiant::new(&self.productions, &self.classes, &self.starting_variable).recognize(&input)
+    }
+
+    // Tests each input, spreading them over the available threads.
+    // The answers are in the same order as the inputs
+    pub fn test_many(&self, inputs: &[Vec<&str>]) -> Vec<bool> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let per_thread = inputs.len().div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = inputs
+                .chunks(per_thread)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|input| self.test(input.clone()))
+                            .collect::<Vec<bool>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    // `test` with the table `parse` fills in, one bool per production and span.
+    // Kept to check the recognizer against and to measure it by
+    #[cfg(test)]
+    pub fn test_naive(&self, input: Vec<&str>) -> bool {
+        if input.is_empty() || self.backend == Backend::Earley {
+            return self.test(input);
+        }
+
+        let (table, _) = self.fill_table(&input);
+        self.accepting_rule(&table).is_some()
+    }
+
+    // A parser that is fed tokens one at a time, see `IncrementalParser`.
+    // It works on the grammar as written, whichever backend this uses
+    pub fn incremental_parser(&self) -> IncrementalParser<'_> {
+        IncrementalParser::new(
+            Earley::new(&self.original, &self.original_start, &self.classes),
+            &self.classes,
+        )
+    }
+
+    // The terminals that can follow the prefix in some string of the language.
+    // Classes and declared tokens are given as written
+    pub fn expected_next(&self, prefix: Vec<&str>) -> BTreeSet<String> {
+        let mut parser = self.incremental_parser();
+        if !prefix.iter().all(|token| parser.push(token)) {
+            return BTreeSet::new();
+        }
+
+        parser.expected_next()
+    }
+
+    // The shortest ways to finish the prefix, see `IncrementalParser::minimal_completions`
+    pub fn minimal_completions(&self, prefix: Vec<&str>, max_len: usize) -> Vec<Vec<String>> {
+        let mut parser = self.incremental_parser();
+        if !prefix.iter().all(|token| parser.push(token)) {
+            return vec![];
+        }
+
+        parser.minimal_completions(max_len)
+    }
+
+    // Splits raw text into the terminals of the grammar, see `Scanner`.
+    // Returns the byte offset of the first text no terminal matches
+    pub fn tokenize(&self, text: &str) -> Result<Vec<String>, usize> {
+        Scanner::new(&self.original, &self.classes, &self.tokens).scan(text)
+    }
+
+    // Tests raw text by tokenizing it first
+    pub fn test_str(&self, text: &str) -> bool {
+        match self.tokenize(text) {
+            Ok(tokens) => self.test(tokens.iter().map(|t| t.as_str()).collect()),
+            Err(_) => false,
+        }
+    }
+
+    // Parses the string using the chosen backend, returning how it was derived.
+    // Earley derivations are already over the grammar as written
+    pub fn parse(&self, input: Vec<&str>) -> Option<ParseTree> {
+        let n = input.len();
+        if n == 0 {
+            return None;
+        }
+
+        if self.backend == Backend::Earley {
+            let mut earley = Earley::new(&self.productions, &self.starting_variable, &self.classes);
+            input.iter().for_each(|token| {
+                earley.push(token);
+            });
+            return earley.parse_tree();
+        }
+
+        let (table, backpointing) = self.fill_table(&input);
+        let rule = self.accepting_rule(&table)?;
+
+        Some(self.build_tree(&input, &backpointing, n - 1, 0, rule))
+    }
+
+    // Parses the string with Viterbi CYK, returning the most probable derivation
+    // and its natural log-probability. Like `parse`, Earley derivations are over the
+    // grammar as written and CYK ones are over the CNF productions
+    pub fn most_probable_parse(&self, input: Vec<&str>) -> Option<(ParseTree, f64)> {
+        let n = input.len();
+        if n == 0 {
+            return None;
+        }
+
+        // Viterbi needs the grammar in CNF
+        if self.backend == Backend::Earley {
+            let cnf = self.clone().prepare(Backend::Cyk);
+            let (tree, log_prob) = cnf.most_probable_parse(input)?;
+            return Some((cnf.restore_tree(&tree), log_prob));
+        }
+
+        let (table, backpointing) = self.fill_viterbi_table(&input);
+        let (rule, log_prob) = self
+            .productions
+            .iter()
+            .enumerate()
+            .filter(|(_, prod)| prod.symbol == self.starting_variable)
+            .map(|(idx, _)| (idx, table[n - 1][0][idx]))
+            .filter(|(_, log_prob)| log_prob.is_finite())
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        Some((
+            self.build_tree(&input, &backpointing, n - 1, 0, rule),
+            log_prob,
+        ))
+    }
+
+    // Like `fill_table`, but table[l][s][v] is the best log-probability of deriving
+    // the span from rule v, or negative infinity if it cannot be derived
+    fn fill_viterbi_table(&self, input: &[&str]) -> (Vec<Vec<Vec<f64>>>, BackPointers) {
+        let n = input.len();
+        let r = self.productions.len();
+
+        let mut table = vec![vec![vec![f64::NEG_INFINITY; r]; n]; n];
+        let mut backpointing = vec![vec![vec![None; r]; n]; n];
+
+        for (s, a) in input.iter().enumerate() {
+            for (v, prod) in self.productions.iter().enumerate() {
+                if prod.value.len() == 1 && matches_terminal(&self.classes, &prod.value[0], a) {
+                    table[0][s][v] = prod.weight.ln();
+                }
+            }
+        }
+
+        for l in 1..n {
+            for s in 0..(n - l) {
+                for p in 0..l {
+                    for (a, b, c) in self.triplets.iter() {
+                        let log_prob = self.productions[*a].weight.ln()
+                            + table[p][s][*b]
+                            + table[l - p - 1][s + p + 1][*c];
+
+                        // Keep the most probable way to derive the span from rule a
+                        if log_prob > table[l][s][*a] {
+                            table[l][s][*a] = log_prob;
+                            backpointing[l][s][*a] = Some((p, *b, *c));
+                        }
+                    }
+                }
+            }
+        }
+
+        (table, backpointing)
+    }
+
+    // table[l][s][v] is true if the span of length l + 1 starting at s can be derived from rule v
+    // backpointing[l][s][v] holds the partition and child rules that derived it
+    fn fill_table(&self, input: &[&str]) -> (Vec<Vec<Vec<bool>>>, BackPointers) {
+        let n = input.len();
+
         // The number of rules
         let r = self.productions.len();
 
         let mut table = vec![vec![vec![false; r]; n]; n];
-        // let backpointing = vec![vec![vec![vec![]]; n]; n];
+        let mut backpointing = vec![vec![vec![None; r]; n]; n];
 
         for (s, a) in input.iter().enumerate() {
             // Find a R_v s.t. R_v -> a_s
             for (v, prod) in self.productions.iter().enumerate() {
-                if prod.value.len() == 1 && prod.value[0] == *a {
+                if prod.value.len() == 1 && matches_terminal(&self.classes, &prod.value[0], a) {
                     table[0][s][v] = true;
                 }
             }
@@ -66,23 +490,137 @@ impl Cfg {
                         // It could be dervied from the current production
                         let can_make_left = table[p][s][*b];
                         let can_make_right = table[l - p - 1][s + p + 1][*c];
-                        if can_make_left && can_make_right {
+                        if can_make_left && can_make_right && !table[l][s][*a] {
                             // substring of length l start at s can be made from rule a
                             table[l][s][*a] = true;
+                            backpointing[l][s][*a] = Some((p, *b, *c));
                         }
                     }
                 }
             }
         }
 
-        // If any start symbol leads to the input string, return true
-        for (idx, prod) in self.productions.iter().enumerate() {
-            if prod.symbol == self.starting_variable && table[n - 1][0][idx] {
-                return true;
+        (table, backpointing)
+    }
+
+    // Finds a rule for the start symbol that derives the whole input
+    fn accepting_rule(&self, table: &[Vec<Vec<bool>>]) -> Option<usize> {
+        let n = table.len();
+        self.productions
+            .iter()
+            .enumerate()
+            .position(|(idx, prod)| prod.symbol == self.starting_variable && table[n - 1][0][idx])
+    }
+
+    fn build_tree(
+        &self,
+        input: &[&str],
+        backpointing: &BackPointers,
+        l: usize,
+        s: usize,
+        rule: usize,
+    ) -> ParseTree {
+        let production = self.productions[rule].clone();
+
+        let children = match backpointing[l][s][rule] {
+            // Only terminal rules fill the bottom row
+            None => vec![ParseTree::Leaf(input[s].to_string())],
+            Some((p, b, c)) => vec![
+                self.build_tree(input, backpointing, p, s, b),
+                self.build_tree(input, backpointing, l - p - 1, s + p + 1, c),
+            ],
+        };
+
+        ParseTree::Node {
+            production,
+            children,
+        }
+    }
+
+    // Turns a derivation over the CNF productions back into one over the grammar as written.
+    // Helper variables are flattened, inlined unit productions are put back
+    // and removed nullable variables are derived to epsilon again
+    pub fn restore_tree(&self, tree: &ParseTree) -> ParseTree {
+        let nullable_witnesses = nullable_witnesses(&self.original);
+        self.restore_node(tree, &nullable_witnesses)
+    }
+
+    fn restore_node(&self, tree: &ParseTree, witnesses: &HashMap<String, usize>) -> ParseTree {
+        let ParseTree::Node {
+            production,
+            children,
+        } = tree
+        else {
+            return tree.clone();
+        };
+
+        let origin = production
+            .origin
+            .as_ref()
+            .expect("Helper productions are flattened into their parent");
+        let written = &self.original[origin.index];
+
+        let mut flattened = Vec::new();
+        children
+            .iter()
+            .for_each(|child| self.flatten_helpers(child, witnesses, &mut flattened));
+
+        let mut restored = self.align_with_written(written, flattened, witnesses);
+
+        // Put back the unit productions, innermost first.
+        // These may have lost nullable variables too, so they are lined up the same way
+        for unit in origin.units.iter().rev() {
+            restored = self.align_with_written(&self.original[*unit], vec![restored], witnesses);
+        }
+
+        restored
+    }
+
+    // Lines the derived symbols up with the written rule.
+    // Anything missing was a nullable variable removed during conversion
+    fn align_with_written(
+        &self,
+        written: &Production,
+        derived: Vec<ParseTree>,
+        witnesses: &HashMap<String, usize>,
+    ) -> ParseTree {
+        let mut derived = derived.into_iter().peekable();
+        let mut children = Vec::new();
+        for symbol in written.value.iter() {
+            if written.is_null() {
+                children.push(ParseTree::Leaf(symbol.clone()));
+            } else if derived
+                .peek()
+                .is_some_and(|child| self.matches_symbol(child, symbol))
+            {
+                children.push(derived.next().unwrap());
+            } else {
+                children.push(epsilon_tree(&self.original, symbol, witnesses));
             }
         }
+        assert!(derived.next().is_none());
 
-        false
+        ParseTree::Node {
+            production: written.clone(),
+            children,
+        }
+    }
+
+    fn flatten_helpers(
+        &self,
+        tree: &ParseTree,
+        witnesses: &HashMap<String, usize>,
+        flattened: &mut Vec<ParseTree>,
+    ) {
+        match tree {
+            ParseTree::Node {
+                production,
+                children,
+            } if production.origin.is_none() => children
+                .iter()
+                .for_each(|child| self.flatten_helpers(child, witnesses, flattened)),
+            _ => flattened.push(self.restore_node(tree, witnesses)),
+        }
     }
 
     fn convert_to_cnf(&mut self) {
@@ -90,6 +628,11 @@ impl Cfg {
         self.remove_start_symbol();
 
         // Step 2: Remove null, unit, and useless productions
+        // A variable whose only production was epsilon has none left after step 2a,
+        // so the variables are taken from before it
+        let variables: HashSet<String> =
+            self.productions.iter().map(|p| p.symbol.clone()).collect();
+
         // Step 2a remove null productions
         self.remove_null_productions();
 
@@ -97,7 +640,7 @@ impl Cfg {
         self.remove_unit_productions();
 
         // Step 2c Remove useless productions
-        self.remove_useless_productions();
+        self.remove_useless_productions(&variables);
 
         // Step 3: Remove terminals from RHS if it exists with a variable
         self.isolate_terminals();
@@ -120,33 +663,40 @@ impl Cfg {
         if contains_start {
             let old_starting_var = self.starting_variable.clone();
 
-            self.starting_variable = "S`".to_string();
-            self.productions.push(Production {
-                symbol: self.starting_variable.clone(),
-                value: vec![old_starting_var],
-            });
+            self.starting_variable = self.symbols.fresh_name("S`");
+            self.productions.push(Production::new(
+                self.starting_variable.clone(),
+                vec![old_starting_var],
+            ));
         }
     }
 
     fn remove_null_productions(&mut self) {
-        let nullable_names: HashSet<_> = self
-            .productions
-            .iter()
-            .filter(|p| p.is_null())
-            .map(|p| p.symbol.clone())
-            .collect();
+        // Variables can be nullable through others, like A in A -> B B, B -> #
+        let epsilon_weights = epsilon_weights(&self.productions);
+        let nullable_names: Vec<_> = nullable_witnesses(&self.productions).into_keys().collect();
 
         for nullable_name in nullable_names {
+            let epsilon_weight = epsilon_weights[&nullable_name];
+
             let new_prods: Vec<Production> = self
                 .productions
                 .iter()
                 .filter(|prod| prod.value.contains(&nullable_name))
-                .flat_map(|prod| prod.remove_null_production(&nullable_name))
+                .flat_map(|prod| prod.remove_null_production(&nullable_name, epsilon_weight))
                 .collect();
 
-            new_prods.iter().for_each(|p| {
-                if !self.productions.contains(p) {
-                    self.productions.push(p.clone())
+            new_prods.into_iter().for_each(|p| {
+                if self.keep_derivations {
+                    self.productions.push(p);
+                    return;
+                }
+
+                match self.productions.iter_mut().find(|existing| **existing == p) {
+                    // The same rule made in two ways keeps the more probable one
+                    Some(existing) if existing.weight < p.weight => *existing = p,
+                    Some(_) => {}
+                    None => self.productions.push(p),
                 }
             });
         }
@@ -155,79 +705,70 @@ impl Cfg {
     }
 
     fn remove_unit_productions(&mut self) {
-        let mut unit_production_pairs: Vec<Production> = vec![];
-        let mut productions_to_add: Vec<Production> = vec![];
-
         // S -> A
-        self.productions.iter().for_each(|prod| {
-            if prod.value.len() == 1 && self.is_variable(prod.value[0].as_str()) {
-                unit_production_pairs.push(prod.clone());
+        let (units, others): (Vec<Production>, Vec<Production>) =
+            self.productions.iter().cloned().partition(|prod| {
+                prod.value.len() == 1 && self.is_variable(prod.value[0].as_str())
+            });
+
+        let chains = if self.keep_derivations {
+            every_unit_chain(&units)
+        } else {
+            most_likely_unit_chains(&units)
+        };
+
+        // Give each variable the productions of every variable it can become
+        let mut productions_to_add: Vec<Production> = vec![];
+        for ((from, to), (chain, weight)) in chains.iter() {
+            // Already has its own productions
+            if from == to {
+                continue;
             }
-        });
 
-        // Remove the unit production
-        self.productions
-            .retain(|p| !unit_production_pairs.contains(p));
+            // Remember the unit productions skipped over to get here
+            let mut chain_units: Vec<usize> = Vec::new();
+            let mut chain_removed: Vec<String> = Vec::new();
+            for unit in chain.iter().map(|i| &units[*i]) {
+                chain_units.extend(unit.unit_chain());
+                if let Some(origin) = &unit.origin {
+                    chain_removed.extend(origin.removed.iter().cloned());
+                }
+            }
 
-        // Add all the productions of the result to the parent
-        for unit_prod in unit_production_pairs {
-            let new_productions = self
-                .productions
-                .iter()
-                .filter(|p| p.symbol == unit_prod.value[0]);
+            for prod in others.iter().filter(|p| p.symbol == *to) {
+                let origin = prod.origin.as_ref().map(|origin| Origin {
+                    index: origin.index,
+                    units: [chain_units.as_slice(), &origin.units].concat(),
+                    removed: [chain_removed.as_slice(), &origin.removed].concat(),
+                });
 
-            for prod in new_productions {
                 productions_to_add.push(Production {
-                    symbol: unit_prod.symbol.clone(),
+                    symbol: from.clone(),
                     value: prod.value.clone(),
-                })
+                    origin,
+                    weight: weight * prod.weight,
+                });
             }
         }
 
-        // If nothing was done, return
-        if productions_to_add.is_empty() {
-            return;
-        }
-
-        productions_to_add
-            .iter()
-            .for_each(|p| self.productions.push(p.clone()));
-
-        // We may have created new unit productions
-        self.remove_unit_productions();
+        self.productions = others;
+        self.productions.extend(productions_to_add);
     }
 
-    fn remove_useless_productions(&mut self) {
-        let mut reachable_vars: Vec<String> = vec![self.starting_variable.clone()];
-
-        loop {
-            let mut new_reachable_vars: Vec<String> = vec![];
-
-            for symbol in reachable_vars.clone() {
-                let prods = self.productions.iter().filter(|p| p.symbol == symbol);
-                for prod in prods {
-                    for s in &prod.value {
-                        if self.is_variable(s.as_str())
-                            && !reachable_vars.contains(s)
-                            && !new_reachable_vars.contains(s)
-                        {
-                            new_reachable_vars.push(s.clone());
-                        }
-                    }
-                }
-            }
-
-            // If there are no new reachable vars, break
-            if new_reachable_vars.is_empty() {
-                break;
-            }
-
-            reachable_vars.extend(new_reachable_vars);
-        }
+    fn remove_useless_productions(&mut self, variables: &HashSet<String>) {
+        // Remove the productions that use a variable which never derives terminals,
+        // including one with no productions left. Without them the variable is gone too
+        let productive = productive(&self.productions);
+        self.productions.retain(|p| {
+            p.value
+                .iter()
+                .chain(std::iter::once(&p.symbol))
+                .all(|s| !variables.contains(s) || productive.contains(s))
+        });
 
         // Remove all variables that are not reachable
-        self.productions
-            .retain(|p| reachable_vars.contains(&p.symbol));
+        let reachable = reachable(&self.productions, &self.starting_variable);
+        self.productions.retain(|p| reachable.contains(&p.symbol));
     }
 
     fn isolate_terminals(&mut self) {
@@ -235,6 +776,8 @@ impl Cfg {
             self.productions.iter().map(|p| p.symbol.clone()).collect();
         let mut to_insert = Vec::new();
         let self_clone = self.clone();
+        // The variable made for each terminal, shared by every production using it
+        let mut wrappers: HashMap<String, String> = HashMap::new();
 
         for prod in self.productions.iter_mut() {
             let mut new_value: Vec<String>;
@@ -249,11 +792,14 @@ impl Cfg {
             prod.value.iter().enumerate().for_each(|(i, s)| {
                 if !variable_names.contains(s) {
                     // Create the new var to hold this terminal
-                    let new_name = format!("{}{}", s, "`");
-                    to_insert.push(Production {
-                        symbol: new_name.clone(),
-                        value: vec![s.clone()],
-                    });
+                    let new_name = wrappers
+                        .entry(s.clone())
+                        .or_insert_with(|| {
+                            let name = self.symbols.fresh_name(&format!("{}`", s));
+                            to_insert.push(Production::new(name.clone(), vec![s.clone()]));
+                            name
+                        })
+                        .clone();
 
                     // Remove the prod and replace with the new var
                     new_value.remove(i);
@@ -264,11 +810,7 @@ impl Cfg {
             prod.value = new_value;
         }
 
-        for prod in to_insert {
-            if !self.productions.contains(&prod) {
-                self.productions.push(prod);
-            }
-        }
+        self.productions.extend(to_insert);
     }
 
     // Make sure that if there is more than one item, its not a terminal
@@ -289,25 +831,28 @@ impl Cfg {
     // This will follow the similar pattern as isolate_terminals
     fn remove_long_productions(&mut self) {
         let mut to_insert = Vec::new();
+        // The variable made for each pair, so the same ending is only split off once
+        let mut helpers: HashMap<Vec<String>, String> = HashMap::new();
         for prod in self.productions.iter_mut() {
             // If the length is longer than 2
             while prod.value.len() > 2 {
                 // Replace 2 of the variables with a new variable
                 let last2 = prod.value.split_off(prod.value.len() - 2);
-                let new_name = last2.join("");
-                to_insert.push(Production {
-                    symbol: new_name.clone(),
-                    value: last2,
-                });
+                let new_name = helpers
+                    .entry(last2.clone())
+                    .or_insert_with(|| {
+                        let name = self.symbols.fresh_name(&last2.join(""));
+                        to_insert.push(Production::new(name.clone(), last2));
+                        name
+                    })
+                    .clone();
 
                 // Replace with the new var
                 prod.value.push(new_name);
             }
         }
 
-        for prod in to_insert {
-            self.productions.push(prod);
-        }
+        self.productions.extend(to_insert);
     }
 
     fn create_triplets(&mut self) {
@@ -332,68 +877,277 @@ impl Cfg {
         }
     }
 
-    #[allow(dead_code)]
-    pub fn generate_sample_langauge(&self, n: usize) -> Vec<String> {
+    // Random strings from the language, see `Generator`. Each one is derived with
+    // about `max_size` productions at most. Declared tokens are never generated, so this is
+    // empty if the language has no strings or all of them use a token
+    pub fn generate_sample_langauge_with_rng<R: Rng>(
+        &self,
+        n: usize,
+        max_size: usize,
+        rng: &mut R,
+    ) -> Vec<String> {
+        // Samples come from the grammar as written, where the weights are still probabilities
+        let generator = Generator::new(&self.original, &self.classes, &self.tokens, max_size);
+
         let mut sample_strings = Vec::new();
         for _ in 0..n {
-            let sample = self.generate_sample_string(vec![self.starting_variable.clone()]);
-            sample_strings.push(sample);
+            match generator.generate(&self.original_start, rng) {
+                Some(sample) => sample_strings.push(sample),
+                None => break,
+            }
         }
 
         sample_strings
     }
 
-    fn generate_sample_string(&self, s: Vec<String>) -> String {
-        for (i, c) in s.iter().enumerate() {
-            if self.is_variable(c) {
-                let prods: Vec<_> = self.productions.iter().filter(|p| &p.symbol == c).collect();
+    pub fn generate_sample_langauge_within(&self, n: usize, max_size: usize) -> Vec<String> {
+        self.generate_sample_langauge_with_rng(n, max_size, &mut rand::thread_rng())
+    }
 
-                // Choose a random production
-                let prod = prods.choose(&mut rand::thread_rng()).unwrap();
+    pub fn generate_sample_langauge(&self, n: usize) -> Vec<String> {
+        self.generate_sample_langauge_within(n, DEFAULT_MAX_SIZE)
+    }
 
-                // Replace the variable with the production
-                // Insert the vector from prod.value into the new_s
-                let mut new_s = s.clone();
-                new_s.splice(i..i + 1, prod.value.clone());
+    // Reproducible samples, each along with its own seed so it can be made again
+    // with `replay_sample`. The same grammar and seed give the same samples on any platform
+    pub fn generate_seeded_samples(&self, n: usize, seed: u64) -> Vec<(u64, String)> {
+        let mut seeds = ChaCha8Rng::seed_from_u64(seed);
 
-                return self.generate_sample_string(new_s);
+        let mut samples = Vec::new();
+        for _ in 0..n {
+            let sample_seed = seeds.next_u64();
+            match self.replay_sample(sample_seed) {
+                Some(sample) => samples.push((sample_seed, sample)),
+                None => break,
             }
         }
 
-        s.join("")
+        samples
+    }
+
+    // The sample `generate_seeded_samples` made from this seed
+    pub fn replay_sample(&self, seed: u64) -> Option<String> {
+        let generator = Generator::new(
+            &self.original,
+            &self.classes,
+            &self.tokens,
+            DEFAULT_MAX_SIZE,
+        );
+        generator.generate(&self.original_start, &mut ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    // How many derivation trees the grammar as written has for strings of `len` terminals.
+    // Each string of an unambiguous grammar has one, so this is then the number of strings.
+    // A nullable variable left out of a string counts as one derivation of epsilon,
+    // and unit productions are not followed round a cycle
+    pub fn count(&self, len: usize) -> BigUint {
+        // Every way of making a CNF production is kept, so each written derivation is counted
+        let cnf = Cfg {
+            keep_derivations: true,
+            ..self.clone()
+        }
+        .rebuild(Backend::Cyk);
+
+        DerivationCounter::new(&cnf.productions, &cnf.triplets, &cnf.classes)
+            .count(&cnf.starting_variable, len)
+    }
+
+    // Problems in the grammar as written, in the order of the lines they are on
+    pub fn lint(&self) -> Vec<Warning> {
+        lint(
+            &self.original,
+            &self.lines,
+            &self.original_start,
+            &self.quoted,
+            &self.tokens,
+        )
+    }
+
+    // Lints a grammar without converting it first. Only fails if it cannot be read at all
+    pub fn lint_str(input: &str) -> Result<Vec<Warning>, GrammarError> {
+        Ok(read_cfg(input, false)?.lint())
+    }
+
+    // Which variables of the grammar as written are productive and reachable,
+    // and whether the language is empty or finite
+    pub fn analyze(&self) -> Analysis {
+        let productive = productive(&self.original);
+        let reachable = reachable(&self.original, &self.original_start);
+        let is_empty = !productive.contains(&self.original_start);
+
+        let strings = self.enumerate();
+        let finite_language = strings.longest().map(|_| {
+            // CNF leaves out the empty string
+            let mut language = Vec::new();
+            if self.accepts_empty() {
+                language.push(vec![]);
+            }
+            language.extend(strings);
+            language
+        });
+
+        Analysis {
+            productive: productive.into_iter().collect(),
+            reachable: reachable.into_iter().collect(),
+            is_empty,
+            finite_language,
+        }
+    }
+
+    // Every string of the language as its terminals, shortest first, see `Enumerate`.
+    // Terminal classes stand for each of their characters
+    pub fn enumerate(&self) -> Enumerate {
+        // Strings are built from the grammar in CNF
+        if self.backend == Backend::Earley {
+            return self.rebuild(Backend::Cyk).enumerate();
+        }
+
+        Enumerate::new(
+            self.productions.clone(),
+            self.classes.clone(),
+            self.starting_variable.clone(),
+        )
+    }
+
+    // A string of exactly `len` terminals, picked uniformly among the derivations of that
+    // length over the CNF productions. For an unambiguous grammar every string of that length
+    // is as likely. None if the language has no strings of that length
+    pub fn generate_of_length<R: Rng>(&self, len: usize, rng: &mut R) -> Option<String> {
+        // Derivations are counted over the grammar in CNF
+        if self.backend == Backend::Earley {
+            return self.rebuild(Backend::Cyk).generate_of_length(len, rng);
+        }
+
+        let mut counter = DerivationCounter::new(&self.productions, &self.triplets, &self.classes);
+        let terminals = counter.sample(&self.starting_variable, len, rng)?;
+        Some(terminals.concat())
+    }
+
+    fn matches_symbol(&self, tree: &ParseTree, symbol: &str) -> bool {
+        match tree {
+            ParseTree::Leaf(token) => matches_terminal(&self.classes, symbol, token),
+            ParseTree::Node { .. } => tree.symbol() == symbol,
+        }
     }
 }
 
-fn read_cfg(input: &str) -> Cfg {
-    let lines: Vec<&str> = input.lines().collect();
+impl FromStr for Cfg {
+    type Err = GrammarError;
+
+    fn from_str(input: &str) -> Result<Cfg, GrammarError> {
+        Cfg::try_new(input)
+    }
+}
 
-    let mut prods: Vec<Production> = Vec::new();
-    let starting_variable = lines[0].split(' ').next().unwrap().to_string();
+// The most likely chain of unit productions from each variable to every variable it can become
+fn most_likely_unit_chains(units: &[Production]) -> Vec<UnitChain> {
+    // A chain never needs to be longer than the number of unit productions,
+    // so cycles like A -> B -> A cannot keep it growing
+    let mut closure: BTreeMap<(String, String), (Vec<usize>, f64)> = BTreeMap::new();
+    for (i, unit) in units.iter().enumerate() {
+        let pair = (unit.symbol.clone(), unit.value[0].clone());
+        if closure
+            .get(&pair)
+            .is_none_or(|(_, weight)| unit.weight > *weight)
+        {
+            closure.insert(pair, (vec![i], unit.weight));
+        }
+    }
 
-    for line in lines {
-        // First char is the variable name
-        let name = line.split(' ').next().unwrap().to_string();
+    loop {
+        let mut longer = Vec::new();
+        for ((from, to), (chain, weight)) in closure.iter() {
+            if chain.len() >= units.len() {
+                continue;
+            }
 
-        let split = line.split(" -> ");
-        let children_str = split.last().unwrap();
+            for (i, unit) in units.iter().enumerate().filter(|(_, u)| u.symbol == *to) {
+                let pair = (from.clone(), unit.value[0].clone());
+                let weight = weight * unit.weight;
+                if closure.get(&pair).is_none_or(|(_, w)| weight > *w) {
+                    longer.push((pair, ([chain.as_slice(), &[i]].concat(), weight)));
+                }
+            }
+        }
 
-        let children_sep = children_str.split(" | ").map(|x| x.trim().split(' '));
+        // Stop once no chain got more likely
+        let mut changed = false;
+        for (pair, (chain, weight)) in longer {
+            if closure.get(&pair).is_none_or(|(_, w)| weight > *w) {
+                closure.insert(pair, (chain, weight));
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
 
-        // Store just the name of the children for now
-        for child in children_sep {
-            let value_vec: Vec<String> = child.map(|x| x.to_string()).collect();
-            let prod = Production {
-                symbol: name.clone(),
-                value: value_vec,
-            };
+    closure.into_iter().collect()
+}
 
-            prods.push(prod);
+// Every chain of unit productions that uses each of them at most once, like
+// `most_likely_unit_chains`. Derivations that go round a cycle of units are left out
+fn every_unit_chain(units: &[Production]) -> Vec<UnitChain> {
+    let mut chains = Vec::new();
+    let mut stack: Vec<(Vec<usize>, f64)> = units
+        .iter()
+        .enumerate()
+        .map(|(i, unit)| (vec![i], unit.weight))
+        .collect();
+
+    while let Some((chain, weight)) = stack.pop() {
+        let to = &units[*chain.last().unwrap()].value[0];
+        for (i, unit) in units.iter().enumerate() {
+            if unit.symbol == *to && !chain.contains(&i) {
+                stack.push(([chain.as_slice(), &[i]].concat(), weight * unit.weight));
+            }
         }
+
+        let from = units[chain[0]].symbol.clone();
+        chains.push(((from, to.clone()), (chain, weight)));
     }
 
-    Cfg {
-        starting_variable,
-        productions: prods,
+    chains
+}
+
+fn read_cfg(input: &str, strict: bool) -> Result<Cfg, GrammarError> {
+    let grammar = read_grammar(input, strict)?;
+    let original = grammar
+        .productions
+        .iter()
+        .map(|p| Production {
+            origin: None,
+            ..p.clone()
+        })
+        .collect();
+
+    Ok(Cfg {
+        original_start: grammar.starting_variable.clone(),
+        starting_variable: grammar.starting_variable,
+        productions: grammar.productions,
         triplets: vec![],
-    }
+        original,
+        classes: grammar.classes,
+        tokens: grammar.tokens,
+        lines: grammar.lines,
+        quoted: grammar.quoted,
+        symbols: SymbolTable::new(),
+        recognizer: Recognizer::default(),
+        backend: Backend::Cyk,
+        keep_derivations: false,
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn cnf_drops_non_generating_variables() {
+    let cfg = Cfg::new("S -> a | A B\nA -> a A\nB -> b");
+    assert!(cfg.productions.iter().all(|p| p.symbol != "A"));
+    assert!(cfg
+        .productions
+        .iter()
+        .all(|p| !p.value.contains(&"A".to_string())));
+    assert!(cfg.test(vec!["a"]));
+    assert!(!cfg.test(vec!["a", "b"]));
 }
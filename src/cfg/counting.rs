@@ -0,0 +1,142 @@
+use num_bigint::{BigUint, RandBigInt};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+
+use super::production::Production;
+use super::terminal_class::TerminalClass;
+
+// Counts the derivations of a grammar in CNF by the length of the string they derive.
+// A class counts once for each of its characters, so every derivation makes one string
+pub struct DerivationCounter<'a> {
+    productions: &'a [Production],
+    triplets: &'a [(usize, usize, usize)],
+    classes: &'a HashMap<String, TerminalClass>,
+    // counts[l][v] is the number of derivations of strings of length l starting with rule v
+    counts: Vec<Vec<BigUint>>,
+}
+
+impl<'a> DerivationCounter<'a> {
+    pub fn new(
+        productions: &'a [Production],
+        triplets: &'a [(usize, usize, usize)],
+        classes: &'a HashMap<String, TerminalClass>,
+    ) -> DerivationCounter<'a> {
+        let r = productions.len();
+
+        // Nothing derives the empty string in CNF
+        let mut counts = vec![vec![BigUint::ZERO; r]];
+        counts.push(
+            productions
+                .iter()
+                .map(|prod| match prod.value.as_slice() {
+                    [terminal] => BigUint::from(Self::choices(classes, terminal).len()),
+                    _ => BigUint::ZERO,
+                })
+                .collect(),
+        );
+
+        DerivationCounter {
+            productions,
+            triplets,
+            classes,
+            counts,
+        }
+    }
+
+    // Derivations of strings of length `len` from the symbol
+    pub fn count(&mut self, symbol: &str, len: usize) -> BigUint {
+        self.extend_to(len);
+        self.productions
+            .iter()
+            .enumerate()
+            .filter(|(_, prod)| prod.symbol == symbol)
+            .map(|(v, _)| &self.counts[len][v])
+            .sum()
+    }
+
+    // A derivation of length `len` from the symbol picked uniformly at random,
+    // read off as its terminals. None if there are no such derivations
+    pub fn sample<R: Rng>(&mut self, symbol: &str, len: usize, rng: &mut R) -> Option<Vec<String>> {
+        self.extend_to(len);
+
+        let starts: Vec<(usize, usize)> = self
+            .productions
+            .iter()
+            .enumerate()
+            .filter(|(_, prod)| prod.symbol == symbol)
+            .map(|(v, _)| (v, len))
+            .collect();
+        let start = pick(&starts, |(v, l)| self.counts[*l][*v].clone(), rng)?;
+
+        // Rules still to be expanded and the length each must derive, the next one last
+        let mut stack = vec![start];
+        let mut terminals = Vec::new();
+        while let Some((v, l)) = stack.pop() {
+            if l == 1 {
+                let prod = &self.productions[v];
+                let choices = Self::choices(self.classes, &prod.value[0]);
+                terminals.push(choices.choose(rng).unwrap().clone());
+                continue;
+            }
+
+            // Every way to split the rule over the length
+            let splits: Vec<(usize, usize, usize)> = self
+                .triplets
+                .iter()
+                .filter(|(a, _, _)| *a == v)
+                .flat_map(|(_, b, c)| (1..l).map(move |k| (*b, *c, k)))
+                .collect();
+            let (b, c, k) = pick(
+                &splits,
+                |(b, c, k)| &self.counts[*k][*b] * &self.counts[l - k][*c],
+                rng,
+            )?;
+
+            stack.push((c, l - k));
+            stack.push((b, k));
+        }
+
+        Some(terminals)
+    }
+
+    // Fills in the counts up to the length
+    fn extend_to(&mut self, len: usize) {
+        for l in self.counts.len()..=len {
+            let mut row = vec![BigUint::ZERO; self.productions.len()];
+            for (a, b, c) in self.triplets.iter() {
+                for k in 1..l {
+                    row[*a] += &self.counts[k][*b] * &self.counts[l - k][*c];
+                }
+            }
+            self.counts.push(row);
+        }
+    }
+
+    // The strings a terminal stands for
+    fn choices(classes: &HashMap<String, TerminalClass>, terminal: &str) -> Vec<String> {
+        match classes.get(terminal) {
+            Some(class) => class.members().iter().map(|c| c.to_string()).collect(),
+            None => vec![terminal.to_string()],
+        }
+    }
+}
+
+// Picks one of the options with a chance proportional to its count
+fn pick<T: Copy, R: Rng>(options: &[T], count: impl Fn(&T) -> BigUint, rng: &mut R) -> Option<T> {
+    let counts: Vec<BigUint> = options.iter().map(&count).collect();
+    let total: BigUint = counts.iter().sum();
+    if total == BigUint::ZERO {
+        return None;
+    }
+
+    let mut target = rng.gen_biguint_below(&total);
+    for (option, count) in options.iter().zip(counts) {
+        if target < count {
+            return Some(*option);
+        }
+        target -= count;
+    }
+
+    unreachable!("the target is below the total")
+}
@@ -0,0 +1,316 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::analysis::productive;
+use super::parse_tree::{epsilon_tree, ParseTree};
+use super::production::{nullable_witnesses, Production};
+use super::terminal_class::{matches_terminal, TerminalClass};
+
+// A production, how much of it has been matched and the column it started in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Item {
+    production: usize,
+    dot: usize,
+    start: usize,
+}
+
+// Why an item was added, kept so the derivation can be rebuilt.
+// Only the first reason is kept, which always points at older items
+#[derive(Clone, Copy, Debug)]
+enum Reason {
+    Predicted,
+    // Matched the last token against the item at `prev` in the previous column
+    Scanned { prev: usize },
+    // Advanced the item at `prev` (column, index) over the finished item at `child` in this column
+    Completed { prev: (usize, usize), child: usize },
+    // Advanced the item at `prev` in this column over a nullable variable
+    SkippedNullable { prev: usize },
+}
+
+#[derive(Clone, Debug, Default)]
+struct Column {
+    items: Vec<Item>,
+    reasons: Vec<Reason>,
+    positions: HashMap<Item, usize>,
+}
+
+impl Column {
+    fn add(&mut self, item: Item, reason: Reason) {
+        if self.positions.contains_key(&item) {
+            return;
+        }
+
+        self.positions.insert(item, self.items.len());
+        self.items.push(item);
+        self.reasons.push(reason);
+    }
+}
+
+// Earley's algorithm, run directly on the productions as they were written.
+// Tokens are pushed one at a time, each adding a column to the chart
+#[derive(Clone, Debug)]
+pub struct Earley<'a> {
+    productions: &'a [Production],
+    starting_variable: String,
+    classes: &'a HashMap<String, TerminalClass>,
+    variables: HashSet<&'a str>,
+    // Productions whose variables all derive some string. Only these are predicted,
+    // so every item in the chart can still be finished
+    usable: Vec<bool>,
+    nullable_witnesses: HashMap<String, usize>,
+    tokens: Vec<String>,
+    columns: Vec<Column>,
+}
+
+#[allow(dead_code)]
+impl<'a> Earley<'a> {
+    pub fn new(
+        productions: &'a [Production],
+        starting_variable: &str,
+        classes: &'a HashMap<String, TerminalClass>,
+    ) -> Earley<'a> {
+        let mut earley = Earley {
+            productions,
+            starting_variable: starting_variable.to_string(),
+            classes,
+            variables: productions.iter().map(|p| p.symbol.as_str()).collect(),
+            usable: usable(productions),
+            nullable_witnesses: nullable_witnesses(productions),
+            tokens: vec![],
+            columns: vec![Column::default()],
+        };
+
+        for (idx, prod) in productions.iter().enumerate() {
+            if prod.symbol == starting_variable && earley.usable[idx] {
+                let item = Item {
+                    production: idx,
+                    dot: 0,
+                    start: 0,
+                };
+                earley.columns[0].add(item, Reason::Predicted);
+            }
+        }
+
+        earley.close(0);
+        earley
+    }
+
+    // Tests if the whole input can be derived from the starting variable
+    pub fn recognize(
+        productions: &[Production],
+        starting_variable: &str,
+        classes: &HashMap<String, TerminalClass>,
+        input: &[&str],
+    ) -> bool {
+        let mut earley = Earley::new(productions, starting_variable, classes);
+        input.iter().all(|token| earley.push(token)) && earley.is_accepted()
+    }
+
+    // Matches the next token, returning false if nothing in the grammar expected it,
+    // that is if the tokens so far are no longer a viable prefix
+    pub fn push(&mut self, token: &str) -> bool {
+        let k = self.columns.len() - 1;
+        let mut column = Column::default();
+
+        for (i, item) in self.columns[k].items.iter().enumerate() {
+            match self.next_symbol(*item) {
+                Some(symbol)
+                    if !self.variables.contains(symbol)
+                        && matches_terminal(self.classes, symbol, token) =>
+                {
+                    let advanced = Item {
+                        dot: item.dot + 1,
+                        ..*item
+                    };
+                    column.add(advanced, Reason::Scanned { prev: i });
+                }
+                _ => {}
+            }
+        }
+
+        self.tokens.push(token.to_string());
+        self.columns.push(column);
+        self.close(k + 1);
+
+        !self.columns[k + 1].items.is_empty()
+    }
+
+    // True if some string of the language starts with the tokens pushed so far.
+    // Every item left in the last column can be finished, so it only has to have one
+    pub fn is_viable_prefix(&self) -> bool {
+        !self.columns.last().unwrap().items.is_empty()
+    }
+
+    // The terminals the items of the last column wait for, as written.
+    // Each one keeps the tokens a viable prefix
+    pub fn expected(&self) -> BTreeSet<&'a str> {
+        self.columns
+            .last()
+            .unwrap()
+            .items
+            .iter()
+            .filter_map(|item| self.next_symbol(*item))
+            .filter(|symbol| !self.variables.contains(symbol))
+            .collect()
+    }
+
+    // True if the tokens pushed so far can be derived from the starting variable
+    pub fn is_accepted(&self) -> bool {
+        self.accepting_item().is_some()
+    }
+
+    // A derivation of the tokens pushed so far, if they are accepted
+    pub fn parse_tree(&self) -> Option<ParseTree> {
+        let i = self.accepting_item()?;
+        Some(self.build_tree(self.columns.len() - 1, i))
+    }
+
+    fn rhs(&self, production: usize) -> &'a [String] {
+        let prod = &self.productions[production];
+        if prod.is_null() {
+            &[]
+        } else {
+            &prod.value
+        }
+    }
+
+    fn next_symbol(&self, item: Item) -> Option<&'a str> {
+        self.rhs(item.production).get(item.dot).map(|s| s.as_str())
+    }
+
+    // Adds every item implied by the items already in column k
+    fn close(&mut self, k: usize) {
+        let mut i = 0;
+        while i < self.columns[k].items.len() {
+            let item = self.columns[k].items[i];
+            match self.next_symbol(item) {
+                None => self.complete(k, i, item),
+                Some(symbol) if self.variables.contains(symbol) => self.predict(k, i, item, symbol),
+                // Terminals are matched by `push`
+                Some(_) => {}
+            }
+
+            i += 1;
+        }
+    }
+
+    fn predict(&mut self, k: usize, i: usize, item: Item, symbol: &str) {
+        for (idx, prod) in self.productions.iter().enumerate() {
+            if prod.symbol == symbol && self.usable[idx] {
+                let predicted = Item {
+                    production: idx,
+                    dot: 0,
+                    start: k,
+                };
+                self.columns[k].add(predicted, Reason::Predicted);
+            }
+        }
+
+        // A nullable variable may also be skipped straight away (Aycock and Horspool).
+        // Completing it later would miss the items that were waiting on it in this column
+        if self.nullable_witnesses.contains_key(symbol) {
+            let advanced = Item {
+                dot: item.dot + 1,
+                ..item
+            };
+            self.columns[k].add(advanced, Reason::SkippedNullable { prev: i });
+        }
+    }
+
+    fn complete(&mut self, k: usize, i: usize, item: Item) {
+        let symbol = self.productions[item.production].symbol.as_str();
+
+        let waiting: Vec<(usize, Item)> = self.columns[item.start]
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| self.next_symbol(**w) == Some(symbol))
+            .map(|(j, w)| (j, *w))
+            .collect();
+
+        for (j, w) in waiting {
+            let advanced = Item {
+                dot: w.dot + 1,
+                ..w
+            };
+            let reason = Reason::Completed {
+                prev: (item.start, j),
+                child: i,
+            };
+            self.columns[k].add(advanced, reason);
+        }
+    }
+
+    fn accepting_item(&self) -> Option<usize> {
+        // Like CYK, the empty string is never accepted
+        if self.tokens.is_empty() {
+            return None;
+        }
+
+        let last = self.columns.last().unwrap();
+        last.items.iter().position(|item| {
+            item.start == 0
+                && self.next_symbol(*item).is_none()
+                && self.productions[item.production].symbol == self.starting_variable
+        })
+    }
+
+    fn build_tree(&self, k: usize, i: usize) -> ParseTree {
+        let production = self.productions[self.columns[k].items[i].production].clone();
+        if production.is_null() {
+            return ParseTree::Node {
+                production,
+                children: vec![ParseTree::Leaf("#".to_string())],
+            };
+        }
+
+        // Walk back from the finished item to where it was predicted,
+        // collecting the children right to left
+        let mut children = Vec::new();
+        let (mut k, mut i) = (k, i);
+        loop {
+            let item = self.columns[k].items[i];
+            match self.columns[k].reasons[i] {
+                Reason::Predicted => break,
+                Reason::Scanned { prev } => {
+                    children.push(ParseTree::Leaf(self.tokens[k - 1].clone()));
+                    k -= 1;
+                    i = prev;
+                }
+                Reason::Completed { prev, child } => {
+                    children.push(self.build_tree(k, child));
+                    (k, i) = prev;
+                }
+                Reason::SkippedNullable { prev } => {
+                    let symbol = &self.rhs(item.production)[item.dot - 1];
+                    children.push(epsilon_tree(
+                        self.productions,
+                        symbol,
+                        &self.nullable_witnesses,
+                    ));
+                    i = prev;
+                }
+            }
+        }
+
+        children.reverse();
+        ParseTree::Node {
+            production,
+            children,
+        }
+    }
+}
+
+// Which productions only use variables that derive some string
+fn usable(productions: &[Production]) -> Vec<bool> {
+    let variables: HashSet<&str> = productions.iter().map(|p| p.symbol.as_str()).collect();
+    let productive = productive(productions);
+    productions
+        .iter()
+        .map(|p| {
+            productive.contains(&p.symbol)
+                && p.value
+                    .iter()
+                    .all(|s| !variables.contains(s.as_str()) || productive.contains(s))
+        })
+        .collect()
+}
@@ -0,0 +1,294 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+
+use super::analysis::productive;
+use super::production::Production;
+use super::terminal_class::TerminalClass;
+
+// Every string of a grammar in CNF in shortlex order: shorter strings first, and strings
+// of the same length ordered by their terminals. The strings of a variable at a length come
+// from every production and split of it, each giving its strings in order, merged together.
+// Nothing is kept but whether a variable has strings of a length, so the next string is
+// worked out when it is asked for. Each string is only yielded once.
+// Ends after the longest string of a finite language
+pub struct Enumerate {
+    grammar: Grammar,
+    starting_variable: String,
+    // The length of the strings in `current`
+    len: usize,
+    // The length of the longest string, if there are finitely many
+    longest: Option<usize>,
+    current: Option<Strings>,
+}
+
+// The productions, with whether each variable has strings of each length worked out so far
+struct Grammar {
+    productions: Vec<Production>,
+    classes: HashMap<String, TerminalClass>,
+    has_strings: HashMap<(String, usize), bool>,
+}
+
+// The strings of a variable at a length, in order
+enum Strings {
+    // The terminals of a variable at length 1
+    Terminals(std::vec::IntoIter<Vec<String>>),
+    // The strings x y of A -> B C split after k, going through every y for each x.
+    // Every x has the same length, so they come in order
+    Split {
+        left: Box<Strings>,
+        x: Option<Vec<String>>,
+        right: Option<Box<Strings>>,
+        right_symbol: String,
+        right_len: usize,
+    },
+    // The smallest next string of any of the sources, skipping the ones equal to it
+    Merge {
+        sources: Vec<Strings>,
+        heads: BinaryHeap<Reverse<(Vec<String>, usize)>>,
+    },
+}
+
+#[allow(dead_code)]
+impl Enumerate {
+    pub fn new(
+        productions: Vec<Production>,
+        classes: HashMap<String, TerminalClass>,
+        starting_variable: String,
+    ) -> Enumerate {
+        let mut enumerate = Enumerate {
+            grammar: Grammar {
+                productions,
+                classes,
+                has_strings: HashMap::new(),
+            },
+            starting_variable,
+            len: 0,
+            longest: None,
+            current: None,
+        };
+        enumerate.longest = enumerate.longest_string();
+        enumerate
+    }
+
+    // The strings of exactly `len` terminals in order, without moving the iterator
+    pub fn of_length(&mut self, len: usize) -> Vec<Vec<String>> {
+        let mut strings = Strings::new(&self.starting_variable, len, &mut self.grammar);
+        std::iter::from_fn(|| strings.next(&mut self.grammar)).collect()
+    }
+
+    // Goes on from the strings of `len` terminals, skipping the shorter ones
+    pub fn starting_at(mut self, len: usize) -> Enumerate {
+        self.len = len.saturating_sub(1);
+        self.current = None;
+        self
+    }
+
+    // The length of the longest string, or None if there are infinitely many strings
+    pub fn longest(&self) -> Option<usize> {
+        self.longest
+    }
+
+    // A variable that can derive itself and some string makes the language infinite,
+    // as long as the starting variable can reach it
+    fn longest_string(&self) -> Option<usize> {
+        let generating = productive(&self.grammar.productions);
+
+        // Only productions that can finish matter
+        let useful: Vec<&Production> = self
+            .grammar
+            .productions
+            .iter()
+            .filter(|p| p.value.len() == 2 && p.value.iter().all(|s| generating.contains(s)))
+            .collect();
+
+        let mut longest: HashMap<&str, usize> = HashMap::new();
+        let mut visiting: HashSet<&str> = HashSet::new();
+        longest_from(
+            &self.starting_variable,
+            &useful,
+            &mut longest,
+            &mut visiting,
+        )
+    }
+}
+
+// The longest string derived from the symbol, or None if it derives strings of any length.
+// `visiting` holds the variables on the way here, meeting one again is a cycle
+fn longest_from<'a>(
+    symbol: &'a str,
+    useful: &[&'a Production],
+    longest: &mut HashMap<&'a str, usize>,
+    visiting: &mut HashSet<&'a str>,
+) -> Option<usize> {
+    if let Some(len) = longest.get(symbol) {
+        return Some(*len);
+    }
+    if !visiting.insert(symbol) {
+        return None;
+    }
+
+    // Terminal productions give strings of length 1
+    let mut len = 1;
+    for prod in useful.iter().filter(|p| p.symbol == symbol) {
+        let left = longest_from(&prod.value[0], useful, longest, visiting)?;
+        let right = longest_from(&prod.value[1], useful, longest, visiting)?;
+        len = len.max(left + right);
+    }
+
+    visiting.remove(symbol);
+    longest.insert(symbol, len);
+    Some(len)
+}
+
+impl Grammar {
+    // Whether the variable derives any string of `len` terminals
+    fn has_strings(&mut self, symbol: &str, len: usize) -> bool {
+        let key = (symbol.to_string(), len);
+        if let Some(has) = self.has_strings.get(&key) {
+            return *has;
+        }
+
+        let mut has = false;
+        for i in 0..self.productions.len() {
+            if self.productions[i].symbol != symbol {
+                continue;
+            }
+
+            has = match self.productions[i].value.clone().as_slice() {
+                [terminal] if len == 1 => self
+                    .classes
+                    .get(terminal)
+                    .is_none_or(|class| !class.members().is_empty()),
+                // Both halves are shorter, so this always finishes
+                [left, right] => {
+                    (1..len).any(|k| self.has_strings(left, k) && self.has_strings(right, len - k))
+                }
+                _ => false,
+            };
+            if has {
+                break;
+            }
+        }
+
+        self.has_strings.insert(key, has);
+        has
+    }
+}
+
+impl Strings {
+    // The strings of `len` terminals derived from the variable
+    fn new(symbol: &str, len: usize, grammar: &mut Grammar) -> Strings {
+        let mut sources = Vec::new();
+
+        let mut terminals: BTreeSet<Vec<String>> = BTreeSet::new();
+        for i in 0..grammar.productions.len() {
+            if grammar.productions[i].symbol != symbol {
+                continue;
+            }
+
+            match grammar.productions[i].value.clone().as_slice() {
+                [terminal] if len == 1 => match grammar.classes.get(terminal) {
+                    Some(class) => {
+                        terminals.extend(class.members().iter().map(|c| vec![c.to_string()]))
+                    }
+                    None => {
+                        terminals.insert(vec![terminal.clone()]);
+                    }
+                },
+                [left, right] => {
+                    // Splits with nothing on one side are never started
+                    for k in 1..len {
+                        if grammar.has_strings(left, k) && grammar.has_strings(right, len - k) {
+                            sources.push(Strings::Split {
+                                left: Box::new(Strings::new(left, k, grammar)),
+                                x: None,
+                                right: None,
+                                right_symbol: right.clone(),
+                                right_len: len - k,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        sources.push(Strings::Terminals(
+            terminals.into_iter().collect::<Vec<_>>().into_iter(),
+        ));
+
+        let mut heads = BinaryHeap::new();
+        for (i, source) in sources.iter_mut().enumerate() {
+            if let Some(string) = source.next(grammar) {
+                heads.push(Reverse((string, i)));
+            }
+        }
+        Strings::Merge { sources, heads }
+    }
+
+    fn next(&mut self, grammar: &mut Grammar) -> Option<Vec<String>> {
+        match self {
+            Strings::Terminals(terminals) => terminals.next(),
+            Strings::Split {
+                left,
+                x,
+                right,
+                right_symbol,
+                right_len,
+            } => loop {
+                if x.is_none() {
+                    *x = Some(left.next(grammar)?);
+                    *right = Some(Box::new(Strings::new(right_symbol, *right_len, grammar)));
+                }
+
+                match right.as_mut().unwrap().next(grammar) {
+                    Some(y) => return Some([x.as_ref().unwrap().as_slice(), &y].concat()),
+                    None => *x = None,
+                }
+            },
+            Strings::Merge { sources, heads } => {
+                let Reverse((string, i)) = heads.pop()?;
+
+                // Every source that has this string next moves on
+                let mut done = vec![i];
+                while heads.peek().is_some_and(|Reverse((s, _))| *s == string) {
+                    let Reverse((_, j)) = heads.pop().unwrap();
+                    done.push(j);
+                }
+                for j in done {
+                    if let Some(next) = sources[j].next(grammar) {
+                        heads.push(Reverse((next, j)));
+                    }
+                }
+
+                Some(string)
+            }
+        }
+    }
+}
+
+impl Iterator for Enumerate {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Vec<String>> {
+        loop {
+            if let Some(string) = self
+                .current
+                .as_mut()
+                .and_then(|strings| strings.next(&mut self.grammar))
+            {
+                return Some(string);
+            }
+
+            if self.longest.is_some_and(|longest| self.len >= longest) {
+                return None;
+            }
+
+            self.len += 1;
+            self.current = Some(Strings::new(
+                &self.starting_variable,
+                self.len,
+                &mut self.grammar,
+            ));
+        }
+    }
+}
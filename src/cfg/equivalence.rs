@@ -0,0 +1,59 @@
+use super::cfg::Cfg;
+
+// The result of comparing two languages up to a length
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Equivalence {
+    // Both grammars accept the same strings of up to this many terminals
+    EquivalentUpTo(usize),
+    // The shortest string, first in shortlex order, that only one of them accepts
+    Counterexample {
+        string: Vec<String>,
+        accepted_by_first: bool,
+    },
+}
+
+// Compares the languages of two grammars on every string of up to `max_len` terminals,
+// the empty string included.
+// The strings of each grammar are enumerated one length at a time and tested against the other
+#[allow(dead_code)]
+pub fn bounded_equivalence(first: &Cfg, second: &Cfg, max_len: usize) -> Equivalence {
+    // Enumeration goes through CNF, which leaves out the empty string
+    if first.accepts_empty() != second.accepts_empty() {
+        return Equivalence::Counterexample {
+            string: vec![],
+            accepted_by_first: first.accepts_empty(),
+        };
+    }
+
+    let mut first_strings = first.enumerate();
+    let mut second_strings = second.enumerate();
+
+    for len in 1..=max_len {
+        let only_first = rejected_by(first_strings.of_length(len), second);
+        let only_second = rejected_by(second_strings.of_length(len), first);
+
+        let counterexample = match (only_first, only_second) {
+            (Some(a), Some(b)) if b < a => Some((b, false)),
+            (Some(a), _) => Some((a, true)),
+            (None, Some(b)) => Some((b, false)),
+            (None, None) => None,
+        };
+
+        if let Some((string, accepted_by_first)) = counterexample {
+            return Equivalence::Counterexample {
+                string,
+                accepted_by_first,
+            };
+        }
+    }
+
+    Equivalence::EquivalentUpTo(max_len)
+}
+
+// The first of the strings that the other grammar rejects
+fn rejected_by(strings: Vec<Vec<String>>, other: &Cfg) -> Option<Vec<String>> {
+    strings
+        .into_iter()
+        .find(|string| !other.test(string.iter().map(|s| s.as_str()).collect()))
+}
@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GrammarErrorKind {
+    // There are no rules at all
+    EmptyGrammar,
+    // A rule starts with `->` instead of the name of a variable
+    MissingName,
+    // A rule has no `->` after the name of its variable
+    MissingArrow,
+    // Nothing between two `|`, or after `->`. Epsilon is written as `%empty` or `#`
+    EmptyAlternative,
+    // A symbol named like a variable that has no rules
+    UndefinedSymbol(String),
+    // The same alternative written twice for a variable
+    DuplicateRule(String),
+    // A quote or a token pattern with nothing closing it on the same line
+    UnterminatedQuote,
+    // A token that cannot appear where it was written
+    UnexpectedToken(String),
+    // A `%` directive other than `%start`
+    UnknownDirective(String),
+    // A quoted terminal that would be read as epsilon or a variable
+    ReservedTerminal(String),
+    // A `(` with no matching `)` in the same rule
+    UnclosedGroup,
+    // A terminal class with a range that runs backwards, like `[z-a]`
+    InvalidClass(String),
+    // A `NAME = /regex/` declaration that is missing its pattern or cannot be compiled
+    InvalidToken(String),
+    // A weight like `[1.5]` that is not a probability
+    InvalidWeight(String),
+    // The weights of a variable's alternatives add up to more than 1
+    WeightsOverOne(String),
+}
+
+// A problem found while reading a grammar. Lines and columns start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrammarError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: GrammarErrorKind,
+}
+
+impl fmt::Display for GrammarErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrammarErrorKind::EmptyGrammar => write!(f, "the grammar has no rules"),
+            GrammarErrorKind::MissingName => write!(f, "expected a variable name before `->`"),
+            GrammarErrorKind::MissingArrow => write!(f, "expected `->` after the variable name"),
+            GrammarErrorKind::EmptyAlternative => {
+                write!(f, "empty alternative, write `%empty` for epsilon")
+            }
+            GrammarErrorKind::UndefinedSymbol(name) => {
+                write!(f, "`{}` is used but has no rules", name)
+            }
+            GrammarErrorKind::DuplicateRule(rule) => write!(f, "`{}` is written twice", rule),
+            GrammarErrorKind::UnterminatedQuote => write!(f, "missing the closing `\"` or `/`"),
+            GrammarErrorKind::UnexpectedToken(token) => write!(f, "unexpected `{}`", token),
+            GrammarErrorKind::UnknownDirective(name) => write!(f, "unknown directive `%{}`", name),
+            GrammarErrorKind::UnclosedGroup => write!(f, "missing the closing `)`"),
+            GrammarErrorKind::InvalidClass(text) => write!(f, "`{}` is not a valid class", text),
+            GrammarErrorKind::InvalidToken(reason) => write!(f, "invalid token: {}", reason),
+            GrammarErrorKind::InvalidWeight(text) => {
+                write!(f, "`[{}]` is not a probability between 0 and 1", text)
+            }
+            GrammarErrorKind::WeightsOverOne(name) => {
+                write!(f, "the weights of `{}` add up to more than 1", name)
+            }
+            GrammarErrorKind::ReservedTerminal(name) => {
+                write!(f, "`\"{}\"` cannot be used as a terminal", name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl Error for GrammarError {}
@@ -0,0 +1,142 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+use super::production::Production;
+use super::terminal_class::TerminalClass;
+
+// Random strings from a grammar as written, picking productions by their weights.
+// Each string is kept to about `max_size` expanded productions: once the shortest way
+// to finish would go over, only the alternatives that finish in time are picked.
+// The text of a declared token is not written out, since only its pattern is known,
+// so alternatives using one are never picked. A language made only of them has no samples
+pub struct Generator<'a> {
+    productions: &'a [Production],
+    classes: &'a HashMap<String, TerminalClass>,
+    // The names of the declared tokens
+    tokens: HashSet<&'a str>,
+    // The fewest productions needed to derive a string of terminals from each variable.
+    // Variables that cannot derive one are left out
+    min_size: HashMap<&'a str, usize>,
+    max_size: usize,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(
+        productions: &'a [Production],
+        classes: &'a HashMap<String, TerminalClass>,
+        tokens: &'a [(String, String)],
+        max_size: usize,
+    ) -> Generator<'a> {
+        let mut generator = Generator {
+            productions,
+            classes,
+            tokens: tokens.iter().map(|(name, _)| name.as_str()).collect(),
+            min_size: HashMap::new(),
+            max_size,
+        };
+
+        // Keep lowering the sizes until nothing changes
+        loop {
+            let mut changed = false;
+            for prod in productions.iter() {
+                let Some(size) = generator.production_size(prod) else {
+                    continue;
+                };
+
+                let current = generator.min_size.get(prod.symbol.as_str());
+                if current.is_none_or(|current| size < *current) {
+                    generator.min_size.insert(&prod.symbol, size);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        generator
+    }
+
+    // A random string derived from the symbol, or None if it derives no strings at all
+    pub fn generate<R: Rng>(&self, symbol: &str, rng: &mut R) -> Option<String> {
+        if self.is_variable(symbol) && !self.min_size.contains_key(symbol) {
+            return None;
+        }
+
+        // Symbols still to be written, the next one last, along with
+        // the fewest productions needed to finish the variables among them
+        let mut stack = vec![symbol];
+        let mut pending = self.min_size.get(symbol).copied().unwrap_or(0);
+        let mut used = 0;
+        let mut output = String::new();
+
+        while let Some(symbol) = stack.pop() {
+            if !self.is_variable(symbol) {
+                output.push_str(&self.terminal(symbol, rng));
+                continue;
+            }
+            pending -= self.min_size[symbol];
+
+            let candidates: Vec<(&Production, usize)> = self
+                .productions
+                .iter()
+                .filter(|p| p.symbol == symbol)
+                .filter_map(|p| Some((p, self.production_size(p)?)))
+                .collect();
+            let fitting: Vec<&(&Production, usize)> = candidates
+                .iter()
+                .filter(|(_, size)| used + size + pending <= self.max_size)
+                .collect();
+
+            let (prod, size) = match fitting.choose_weighted(rng, |(p, _)| p.weight) {
+                Ok(chosen) => **chosen,
+                // Over the budget already, so finish as soon as possible
+                Err(_) => *candidates.iter().min_by_key(|(_, size)| *size).unwrap(),
+            };
+
+            used += 1;
+            pending += size - 1;
+            if !prod.is_null() {
+                stack.extend(prod.value.iter().rev().map(|s| s.as_str()));
+            }
+        }
+
+        Some(output)
+    }
+
+    fn is_variable(&self, symbol: &str) -> bool {
+        self.productions.iter().any(|p| p.symbol == symbol)
+    }
+
+    // The fewest productions needed to derive terminals through this production,
+    // itself included, if every variable in it is known to derive some and it uses no tokens
+    fn production_size(&self, prod: &Production) -> Option<usize> {
+        if prod.is_null() {
+            return Some(1);
+        }
+
+        prod.value.iter().try_fold(1, |total, s| {
+            if self.is_variable(s) {
+                Some(total + self.min_size.get(s.as_str())?)
+            } else if self.tokens.contains(s.as_str()) {
+                None
+            } else {
+                Some(total)
+            }
+        })
+    }
+
+    // Classes stand for one of their characters
+    fn terminal<R: Rng>(&self, terminal: &str, rng: &mut R) -> String {
+        match self.classes.get(terminal) {
+            Some(class) => class
+                .members()
+                .choose(rng)
+                .map(|m| m.to_string())
+                .unwrap_or_default(),
+            None => terminal.to_string(),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::earley::Earley;
+use super::terminal_class::TerminalClass;
+
+// Recognizes a string as its tokens arrive, see `Cfg::incremental_parser`.
+// Each token adds one column to an Earley chart over the grammar as written,
+// so nothing already matched is looked at again
+#[derive(Clone, Debug)]
+pub struct IncrementalParser<'a> {
+    earley: Earley<'a>,
+    classes: &'a HashMap<String, TerminalClass>,
+}
+
+#[allow(dead_code)]
+impl<'a> IncrementalParser<'a> {
+    pub fn new(
+        earley: Earley<'a>,
+        classes: &'a HashMap<String, TerminalClass>,
+    ) -> IncrementalParser<'a> {
+        IncrementalParser { earley, classes }
+    }
+
+    // Matches the next token, returning whether the tokens so far are still a viable prefix.
+    // Once they are not, no later token can make them one
+    pub fn push(&mut self, token: &str) -> bool {
+        self.earley.push(token)
+    }
+
+    // True if the tokens pushed so far are a string of the language
+    pub fn is_accepted(&self) -> bool {
+        self.earley.is_accepted()
+    }
+
+    // True if some string of the language starts with the tokens pushed so far
+    pub fn is_viable_prefix(&self) -> bool {
+        self.earley.is_viable_prefix()
+    }
+
+    // The terminals that can come next, as written, so a class stands for its characters.
+    // Empty once the tokens are not a viable prefix
+    pub fn expected_next(&self) -> BTreeSet<String> {
+        self.earley
+            .expected()
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    // The shortest ways to finish the tokens pushed so far into a string of the language,
+    // in order, as long as they take at most `max_len` more terminals. These are real tokens:
+    // a class is finished with its first character, so `[a-z]` gives `a`.
+    // Every terminal that can come next is tried in turn, so this grows quickly with `max_len`
+    pub fn minimal_completions(&self, max_len: usize) -> Vec<Vec<String>> {
+        let mut level: Vec<(Vec<String>, IncrementalParser<'a>)> = vec![(vec![], self.clone())];
+
+        for _ in 0..=max_len {
+            let done: BTreeSet<Vec<String>> = level
+                .iter()
+                .filter(|(_, parser)| parser.is_accepted())
+                .map(|(completion, _)| completion.clone())
+                .collect();
+            if !done.is_empty() {
+                return done.into_iter().collect();
+            }
+
+            // Every expected terminal keeps the prefix viable. A terminal and a class
+            // can stand for the same token, which only needs trying once
+            let mut next = Vec::new();
+            for (completion, parser) in level.iter() {
+                let tokens: BTreeSet<String> = parser
+                    .expected_next()
+                    .iter()
+                    .filter_map(|terminal| self.representative(terminal))
+                    .collect();
+
+                for token in tokens {
+                    let mut parser = parser.clone();
+                    parser.push(&token);
+                    let mut completion = completion.clone();
+                    completion.push(token);
+                    next.push((completion, parser));
+                }
+            }
+            level = next;
+        }
+
+        vec![]
+    }
+
+    // A token the terminal matches: the first character of a class, or the terminal itself.
+    // None for a class without printable characters
+    fn representative(&self, terminal: &str) -> Option<String> {
+        match self.classes.get(terminal) {
+            Some(class) => class.members().first().map(|c| c.to_string()),
+            None => Some(terminal.to_string()),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use super::production::Production;
+use super::terminal_class::{matches_terminal, TerminalClass};
+
+// The CYK chart of a grammar in CNF, summing over derivations instead of picking one
+pub struct InsideOutside<'a> {
+    pub productions: &'a [Production],
+    pub triplets: &'a [(usize, usize, usize)],
+    pub classes: &'a HashMap<String, TerminalClass>,
+    pub starting_variable: &'a str,
+}
+
+impl InsideOutside<'_> {
+    // How many times each production is expected to be used deriving the input,
+    // along with the natural log of the input's probability.
+    // Returns None if the input cannot be derived
+    pub fn expected_counts(&self, input: &[&str]) -> Option<(Vec<f64>, f64)> {
+        let n = input.len();
+        let r = self.productions.len();
+        if n == 0 {
+            return None;
+        }
+
+        let inside = self.inside(input);
+        let total: f64 = (0..r)
+            .filter(|v| self.productions[*v].symbol == self.starting_variable)
+            .map(|v| inside[n - 1][0][v])
+            .sum();
+        if total == 0.0 {
+            return None;
+        }
+
+        let outside = self.outside(&inside);
+
+        let mut counts = vec![0.0; r];
+        for l in 0..n {
+            for s in 0..(n - l) {
+                for (v, count) in counts.iter_mut().enumerate() {
+                    *count += outside[l][s][v] * inside[l][s][v] / total;
+                }
+            }
+        }
+
+        Some((counts, total.ln()))
+    }
+
+    // inside[l][s][v] is the probability of deriving the span of length l + 1
+    // starting at s with rule v first
+    fn inside(&self, input: &[&str]) -> Vec<Vec<Vec<f64>>> {
+        let n = input.len();
+        let r = self.productions.len();
+        let mut inside = vec![vec![vec![0.0; r]; n]; n];
+
+        for (s, a) in input.iter().enumerate() {
+            for (v, prod) in self.productions.iter().enumerate() {
+                if prod.value.len() == 1 && matches_terminal(self.classes, &prod.value[0], a) {
+                    inside[0][s][v] = prod.weight;
+                }
+            }
+        }
+
+        for l in 1..n {
+            for s in 0..(n - l) {
+                for p in 0..l {
+                    for (a, b, c) in self.triplets.iter() {
+                        inside[l][s][*a] += self.productions[*a].weight
+                            * inside[p][s][*b]
+                            * inside[l - p - 1][s + p + 1][*c];
+                    }
+                }
+            }
+        }
+
+        inside
+    }
+
+    // outside[l][s][v] is the probability of deriving everything around the span
+    // with the variable of rule v in its place. Longer spans are finished first
+    fn outside(&self, inside: &[Vec<Vec<f64>>]) -> Vec<Vec<Vec<f64>>> {
+        let n = inside.len();
+        let r = self.productions.len();
+        let mut outside = vec![vec![vec![0.0; r]; n]; n];
+
+        for (v, prod) in self.productions.iter().enumerate() {
+            if prod.symbol == self.starting_variable {
+                outside[n - 1][0][v] = 1.0;
+            }
+        }
+
+        for l in (1..n).rev() {
+            for s in 0..(n - l) {
+                for p in 0..l {
+                    for (a, b, c) in self.triplets.iter() {
+                        let around = outside[l][s][*a] * self.productions[*a].weight;
+                        if around == 0.0 {
+                            continue;
+                        }
+
+                        let left = inside[p][s][*b];
+                        let right = inside[l - p - 1][s + p + 1][*c];
+                        outside[p][s][*b] += around * right;
+                        outside[l - p - 1][s + p + 1][*c] += around * left;
+                    }
+                }
+            }
+        }
+
+        outside
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use super::analysis::{productive, reachable};
+use super::production::{nullable_witnesses, Production};
+use super::reader::looks_like_variable;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WarningKind {
+    // A variable the starting variable never leads to
+    Unreachable(String),
+    // A variable that never derives a string of terminals
+    Unproductive(String),
+    // The same alternative written again for a variable
+    DuplicateAlternative(String),
+    // A name that looks like a variable but has no rules, so it is read as a terminal
+    UndefinedSymbol(String),
+    // Unit productions that lead back to where they started, as the variables on the way
+    UnitCycle(Vec<String>),
+    // Variables that can start with themselves, as the variables on the way
+    LeftRecursion(Vec<String>),
+    // An alternative whose bare `(` or `)` has no partner, like a group written with spaces
+    UnbalancedParenthesis(String),
+    // A terminal ending in `?`, `*` or `+`, which is read as it is written
+    OperatorOnTerminal(String),
+}
+
+// A problem that does not stop the grammar from being read. Lines start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub line: usize,
+    pub kind: WarningKind,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarningKind::Unreachable(name) => {
+                write!(f, "`{}` is never reached from the starting variable", name)
+            }
+            WarningKind::Unproductive(name) => {
+                write!(f, "`{}` never derives a string of terminals", name)
+            }
+            WarningKind::DuplicateAlternative(rule) => {
+                write!(f, "`{}` is written more than once", rule)
+            }
+            WarningKind::UndefinedSymbol(name) => {
+                write!(f, "`{}` has no rules and is read as a terminal", name)
+            }
+            WarningKind::UnitCycle(cycle) => {
+                write!(
+                    f,
+                    "unit productions go round in a cycle: {}",
+                    cycle.join(" -> ")
+                )
+            }
+            WarningKind::UnbalancedParenthesis(rule) => {
+                write!(
+                    f,
+                    "`{}` has a parenthesis without its pair, read as a terminal. \
+                     A group is written against what it holds, like `(A | B)`",
+                    rule
+                )
+            }
+            WarningKind::OperatorOnTerminal(terminal) => {
+                write!(
+                    f,
+                    "`{}` is read as a terminal, `?`, `*` and `+` only apply to variables, \
+                     quoted terminals, classes and groups",
+                    terminal
+                )
+            }
+            WarningKind::LeftRecursion(cycle) => {
+                write!(
+                    f,
+                    "`{}` is left recursive: {}",
+                    cycle[0],
+                    cycle.join(" -> ")
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+// Checks the productions as written, `lines` holding the line of each one
+pub fn lint(
+    productions: &[Production],
+    lines: &[usize],
+    starting_variable: &str,
+    quoted: &HashSet<String>,
+    tokens: &[(String, String)],
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut warn = |index: usize, kind: WarningKind| {
+        warnings.push(Warning {
+            line: lines[index],
+            kind,
+        })
+    };
+
+    // Each variable with the first production written for it
+    let mut variables: Vec<(&str, usize)> = Vec::new();
+    for (i, prod) in productions.iter().enumerate() {
+        if !variables.iter().any(|(name, _)| *name == prod.symbol) {
+            variables.push((&prod.symbol, i));
+        }
+    }
+    let is_variable = |s: &str| variables.iter().any(|(name, _)| *name == s);
+
+    let productive = productive(productions);
+    let reachable = reachable(productions, starting_variable);
+    for (name, first) in variables.iter() {
+        if !reachable.contains(*name) {
+            warn(*first, WarningKind::Unreachable(name.to_string()));
+        }
+        if !productive.contains(*name) {
+            warn(*first, WarningKind::Unproductive(name.to_string()));
+        }
+    }
+
+    let mut seen: HashSet<&Production> = HashSet::new();
+    let mut undefined: HashSet<(&str, usize)> = HashSet::new();
+    let mut unbalanced: HashSet<usize> = HashSet::new();
+    for (i, prod) in productions.iter().enumerate() {
+        if !seen.insert(prod) {
+            let rule = format!("{} -> {}", prod.symbol, prod.value.join(" "));
+            warn(i, WarningKind::DuplicateAlternative(rule));
+        }
+
+        // Once for each line the name is used on
+        for symbol in prod.value.iter() {
+            if looks_like_variable(symbol)
+                && !is_variable(symbol)
+                && !quoted.contains(symbol)
+                && !tokens.iter().any(|(name, _)| name == symbol)
+                && undefined.insert((symbol, lines[i]))
+            {
+                warn(i, WarningKind::UndefinedSymbol(symbol.clone()));
+            }
+
+            let stem = symbol.trim_end_matches(['?', '*', '+']);
+            if !stem.is_empty() && stem != symbol && !quoted.contains(symbol) {
+                warn(i, WarningKind::OperatorOnTerminal(symbol.clone()));
+            }
+        }
+
+        // A group split by spaces leaves `(` and `)` in different alternatives.
+        // Once for each line
+        let mut depth: i32 = 0;
+        for symbol in prod.value.iter().filter(|s| !quoted.contains(*s)) {
+            match symbol.as_str() {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            if depth < 0 {
+                break;
+            }
+        }
+        if depth != 0 && unbalanced.insert(lines[i]) {
+            let rule = format!("{} -> {}", prod.symbol, prod.value.join(" "));
+            warn(i, WarningKind::UnbalancedParenthesis(rule));
+        }
+    }
+
+    // A unit production leads from its variable to the one it is made of
+    let units: Vec<(usize, &str)> = productions
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.value.len() == 1 && is_variable(&p.value[0]))
+        .map(|(i, p)| (i, p.value[0].as_str()))
+        .collect();
+    for cycle in cycles(productions, &variables, &units) {
+        warn(cycle[0], WarningKind::UnitCycle(path(productions, &cycle)));
+    }
+
+    // A production leads to each variable that can come first in it,
+    // which is any variable with only nullable variables in front of it
+    let nullable = nullable_witnesses(productions);
+    let mut left_corners: Vec<(usize, &str)> = Vec::new();
+    for (i, prod) in productions.iter().enumerate() {
+        for symbol in prod.value.iter() {
+            if !is_variable(symbol) {
+                break;
+            }
+
+            left_corners.push((i, symbol));
+            if !nullable.contains_key(symbol) {
+                break;
+            }
+        }
+    }
+    for cycle in cycles(productions, &variables, &left_corners) {
+        // Already reported as a unit cycle
+        if cycle.iter().all(|i| units.iter().any(|(u, _)| u == i)) {
+            continue;
+        }
+
+        warn(
+            cycle[0],
+            WarningKind::LeftRecursion(path(productions, &cycle)),
+        );
+    }
+
+    warnings.sort_by_key(|w| w.line);
+    warnings
+}
+
+// The productions along the shortest way from each variable back to itself,
+// following `edges` of (production, variable it leads to).
+// A variable already on a cycle found before does not start another one
+fn cycles(
+    productions: &[Production],
+    variables: &[(&str, usize)],
+    edges: &[(usize, &str)],
+) -> Vec<Vec<usize>> {
+    let mut cycles: Vec<Vec<usize>> = Vec::new();
+    let mut on_cycle: HashSet<&str> = HashSet::new();
+
+    for (start, _) in variables.iter() {
+        if on_cycle.contains(start) {
+            continue;
+        }
+
+        // The production each variable was first reached by
+        let mut came_from: HashMap<&str, usize> = HashMap::new();
+        let mut queue = VecDeque::from([*start]);
+        let mut last = None;
+        'search: while let Some(symbol) = queue.pop_front() {
+            for (i, to) in edges.iter() {
+                if productions[*i].symbol != symbol {
+                    continue;
+                }
+                if to == start {
+                    last = Some(*i);
+                    break 'search;
+                }
+                if !came_from.contains_key(to) {
+                    came_from.insert(to, *i);
+                    queue.push_back(to);
+                }
+            }
+        }
+
+        // Walk back from the production that closes the cycle
+        let Some(last) = last else {
+            continue;
+        };
+        let mut cycle = vec![last];
+        let mut symbol = productions[last].symbol.as_str();
+        while symbol != *start {
+            let i = came_from[symbol];
+            cycle.push(i);
+            symbol = &productions[i].symbol;
+        }
+        cycle.reverse();
+
+        on_cycle.extend(cycle.iter().map(|i| productions[*i].symbol.as_str()));
+        cycles.push(cycle);
+    }
+
+    cycles
+}
+
+// The variables a cycle goes through, ending where it started
+fn path(productions: &[Production], cycle: &[usize]) -> Vec<String> {
+    let mut path: Vec<String> = cycle
+        .iter()
+        .map(|i| productions[*i].symbol.clone())
+        .collect();
+    path.push(path[0].clone());
+    path
+}
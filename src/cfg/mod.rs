@@ -1,5 +1,24 @@
+pub mod analysis;
+#[allow(clippy::module_inception)]
 pub mod cfg;
+pub mod counting;
+pub mod earley;
+pub mod enumerate;
+pub mod equivalence;
+pub mod error;
+pub mod generator;
+pub mod incremental;
+pub mod inside_outside;
+pub mod lint;
+pub mod parse_tree;
 pub mod production;
+pub mod reader;
+pub mod recognizer;
+pub mod scanner;
+pub mod symbol_table;
+pub mod terminal_class;
+pub mod valiant;
+pub mod writer;
 
 #[cfg(test)]
 pub mod test;
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use super::production::Production;
+
+// A derivation of some input, as produced by `Cfg::parse`
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseTree {
+    // An expanded variable. There is one child per symbol in `production.value`
+    Node {
+        production: Production,
+        children: Vec<ParseTree>,
+    },
+    // A terminal taken straight from the input
+    Leaf(String),
+}
+
+#[allow(dead_code)]
+impl ParseTree {
+    // The variable this tree was derived from, or the terminal for a leaf
+    pub fn symbol(&self) -> &str {
+        match self {
+            ParseTree::Node { production, .. } => production.symbol.as_str(),
+            ParseTree::Leaf(terminal) => terminal.as_str(),
+        }
+    }
+
+    // The terminals of the tree read left to right, which is the parsed input
+    pub fn leaves(&self) -> Vec<&str> {
+        match self {
+            ParseTree::Node { children, .. } => {
+                children.iter().flat_map(|child| child.leaves()).collect()
+            }
+            // Epsilon does not show up in the input
+            ParseTree::Leaf(terminal) if terminal == "#" => vec![],
+            ParseTree::Leaf(terminal) => vec![terminal.as_str()],
+        }
+    }
+
+    pub fn print(&self) {
+        self.print_indented(0);
+    }
+
+    fn print_indented(&self, depth: usize) {
+        let indent = "  ".repeat(depth);
+        match self {
+            ParseTree::Node {
+                production,
+                children,
+            } => {
+                println!(
+                    "{}{} -> {}",
+                    indent,
+                    production.symbol,
+                    production.value.join(" ")
+                );
+                children
+                    .iter()
+                    .for_each(|child| child.print_indented(depth + 1));
+            }
+            ParseTree::Leaf(terminal) => println!("{}{}", indent, terminal),
+        }
+    }
+}
+
+// A derivation of the empty string from a nullable variable,
+// using the witnesses found by `nullable_witnesses`
+pub fn epsilon_tree(
+    productions: &[Production],
+    symbol: &str,
+    witnesses: &HashMap<String, usize>,
+) -> ParseTree {
+    let index = *witnesses
+        .get(symbol)
+        .expect("Only nullable variables derive epsilon");
+    let production = productions[index].clone();
+
+    let children = production
+        .value
+        .iter()
+        .map(|s| {
+            if production.is_null() {
+                ParseTree::Leaf(s.clone())
+            } else {
+                epsilon_tree(productions, s, witnesses)
+            }
+        })
+        .collect();
+
+    ParseTree::Node {
+        production,
+        children,
+    }
+}
@@ -1,64 +1,238 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+// Where a production came from in the grammar as it was written
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Origin {
+    // Index of the written production this was derived from
+    pub index: usize,
+    // Written productions that were inlined in front of it as unit productions, outermost first
+    pub units: Vec<usize>,
+    // Nullable variables left out of it, each standing for an epsilon derivation
+    pub removed: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
 pub struct Production {
     pub symbol: String,
     pub value: Vec<String>,
+    // None for helpers made up during CNF conversion
+    pub origin: Option<Origin>,
+    // The probability of picking this production for its variable.
+    // After CNF conversion it also covers the unit and null productions folded into it
+    pub weight: f64,
+}
+
+// Productions are compared by their rule alone so that the same rule
+// derived in two different ways is only kept once
+impl PartialEq for Production {
+    fn eq(&self, other: &Self) -> bool {
+        self.symbol == other.symbol && self.value == other.value
+    }
+}
+
+impl Eq for Production {}
+
+impl Hash for Production {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.symbol.hash(state);
+        self.value.hash(state);
+    }
 }
 
 impl Production {
+    pub fn new(symbol: String, value: Vec<String>) -> Production {
+        Production {
+            symbol,
+            value,
+            origin: None,
+            weight: 1.0,
+        }
+    }
+
+    // The written unit productions that lead to this one, outermost first
+    pub fn unit_chain(&self) -> Vec<usize> {
+        match &self.origin {
+            Some(origin) => {
+                let mut chain = origin.units.clone();
+                chain.push(origin.index);
+                chain
+            }
+            None => vec![],
+        }
+    }
+
     pub fn is_null(&self) -> bool {
         self.value.len() == 1 && self.value[0] == "#"
     }
 
-    pub fn remove_null_production(&self, nullable_name: &str) -> Vec<Production> {
-        // Case where there is only 1 production
-        let mut new_prods: HashSet<Production> = HashSet::new();
-
-        // Add all productions with nullable prod removed
+    // Every way of leaving out the nullable variable at least once, where `epsilon_weight`
+    // is the probability of it deriving epsilon. Leaving out different occurrences
+    // can give the same rule, which then comes up once for each
+    pub fn remove_null_production(
+        &self,
+        nullable_name: &str,
+        epsilon_weight: f64,
+    ) -> Vec<Production> {
         permute_without(self.value.clone(), nullable_name)
             .iter()
-            .filter(|vec| !vec.is_empty())
-            .map(|vec| Production {
-                symbol: self.symbol.clone(),
-                value: vec.clone(),
+            .filter(|vec| !vec.is_empty() && vec.len() < self.value.len())
+            .map(|vec| {
+                let removed = self.value.len() - vec.len();
+                let origin = self.origin.clone().map(|mut origin| {
+                    origin
+                        .removed
+                        .extend(std::iter::repeat_n(nullable_name.to_string(), removed));
+                    origin
+                });
+
+                Production {
+                    symbol: self.symbol.clone(),
+                    value: vec.clone(),
+                    origin,
+                    weight: self.weight * epsilon_weight.powi(removed as i32),
+                }
             })
-            .for_each(|p| {
-                new_prods.insert(p);
-            });
+            .collect()
+    }
+}
+
+// For each nullable variable, a production that derives epsilon in the fewest steps
+pub fn nullable_witnesses(productions: &[Production]) -> HashMap<String, usize> {
+    let mut witnesses: HashMap<String, usize> = HashMap::new();
+
+    loop {
+        let mut new_witnesses = HashMap::new();
+
+        for (idx, prod) in productions.iter().enumerate() {
+            if witnesses.contains_key(&prod.symbol) || new_witnesses.contains_key(&prod.symbol) {
+                continue;
+            }
 
-        new_prods.into_iter().collect()
+            if prod.is_null() || prod.value.iter().all(|s| witnesses.contains_key(s)) {
+                new_witnesses.insert(prod.symbol.clone(), idx);
+            }
+        }
+
+        // If there are no new nullable vars, break
+        if new_witnesses.is_empty() {
+            break;
+        }
+
+        witnesses.extend(new_witnesses);
     }
+
+    witnesses
 }
 
-fn permute_without(strings: Vec<String>, to_remove: &str) -> Vec<Vec<String>> {
-    let mut perms = HashSet::new();
+// The probability of each nullable variable deriving epsilon. A variable can be nullable
+// through others, maybe through itself, so the values are raised from 0 until they settle
+pub fn epsilon_weights(productions: &[Production]) -> HashMap<String, f64> {
+    let nullable = nullable_witnesses(productions);
+    let mut weights: HashMap<String, f64> = nullable.keys().map(|s| (s.clone(), 0.0)).collect();
 
-    if !strings.iter().any(|c| c == to_remove) {
-        return vec![strings];
+    for _ in 0..1000 {
+        let mut next: HashMap<String, f64> = weights.keys().map(|s| (s.clone(), 0.0)).collect();
+        for prod in productions
+            .iter()
+            .filter(|p| nullable.contains_key(&p.symbol))
+        {
+            let weight = if prod.is_null() {
+                prod.weight
+            } else if prod.value.iter().all(|s| nullable.contains_key(s)) {
+                prod.value.iter().map(|s| weights[s]).product::<f64>() * prod.weight
+            } else {
+                continue;
+            };
+            *next.get_mut(&prod.symbol).unwrap() += weight;
+        }
+
+        let settled = next.iter().all(|(s, w)| (w - weights[s]).abs() < 1e-12);
+        weights = next;
+        if settled {
+            break;
+        }
     }
 
-    strings.iter().enumerate().for_each(|(index, c)| {
-        if c == to_remove {
-            // Remvoe this char from the vec
-            let mut new_chars = strings.clone();
+    weights
+}
+
+// For each nullable variable, how many times each production is expected to be used
+// deriving epsilon from it, given the weights. Raised from 0 like `epsilon_weights`
+pub fn epsilon_counts(productions: &[Production]) -> HashMap<String, Vec<f64>> {
+    let weights = epsilon_weights(productions);
+    let mut counts: HashMap<String, Vec<f64>> = weights
+        .keys()
+        .map(|s| (s.clone(), vec![0.0; productions.len()]))
+        .collect();
+
+    for _ in 0..1000 {
+        let mut next = counts.clone();
+        next.values_mut()
+            .for_each(|c| c.iter_mut().for_each(|c| *c = 0.0));
 
-            // Add the case where it is not remove
-            perms.insert(new_chars.clone());
+        for (idx, prod) in productions.iter().enumerate() {
+            if !weights.contains_key(&prod.symbol)
+                || weights[&prod.symbol] == 0.0
+                || !prod.is_null() && !prod.value.iter().all(|s| weights.contains_key(s))
+            {
+                continue;
+            }
 
-            // Add the case where it is removed
-            new_chars.remove(index);
-            perms.insert(new_chars.clone());
+            // The share of the variable's epsilon derivations that start with this production
+            let share = if prod.is_null() {
+                prod.weight
+            } else {
+                prod.value.iter().map(|s| weights[s]).product::<f64>() * prod.weight
+            } / weights[&prod.symbol];
 
-            // Recursively remove the rest
-            let permuted = permute_without(new_chars, to_remove);
+            let count = next.get_mut(&prod.symbol).unwrap();
+            count[idx] += share;
+            if !prod.is_null() {
+                for symbol in prod.value.iter() {
+                    for (c, inner) in count.iter_mut().zip(counts[symbol].iter()) {
+                        *c += share * inner;
+                    }
+                }
+            }
+        }
 
-            // Add each case to the perms
-            perms.extend(permuted);
+        let settled = next.iter().all(|(s, c)| {
+            c.iter()
+                .zip(counts[s].iter())
+                .all(|(a, b)| (a - b).abs() < 1e-12)
+        });
+        counts = next;
+        if settled {
+            break;
         }
-    });
+    }
+
+    counts
+}
+
+// Every way of keeping or leaving out each occurrence of `to_remove`, the original among them.
+// Each choice of occurrences gives its own result, even when two of them read the same
+fn permute_without(strings: Vec<String>, to_remove: &str) -> Vec<Vec<String>> {
+    let mut perms: Vec<Vec<String>> = vec![vec![]];
+
+    for s in strings {
+        if s == to_remove {
+            // Once with it kept and once with it left out
+            perms = perms
+                .into_iter()
+                .flat_map(|perm| {
+                    let mut kept = perm.clone();
+                    kept.push(s.clone());
+                    [kept, perm]
+                })
+                .collect();
+        } else {
+            perms.iter_mut().for_each(|perm| perm.push(s.clone()));
+        }
+    }
 
-    perms.into_iter().collect()
+    perms
 }
 
 #[cfg(test)]
@@ -0,0 +1,854 @@
+// Reads grammar files.
+//
+// Each rule is `NAME -> alternative | alternative ...`, where symbols are separated by spaces.
+// Symbols named in capitals with rules of their own are variables, everything else is a terminal.
+//
+//     // Comments run to the end of the line
+//     %start PROGRAM
+//     PROGRAM -> STATEMENT ";" PROGRAM
+//              | %empty
+//     STATEMENT -> print "(" ID ")" | ID "->" ID
+//
+// - Terminals can be quoted to use spaces, `|`, `->`, `#`, `%` or `//` in them.
+//   `\"` and `\\` escape a quote and a backslash
+// - `%empty` or `#` is epsilon
+// - A line starting with `|` continues the alternatives of the rule above it
+// - `%start NAME` picks the starting variable, otherwise it is the first rule's variable
+// - `X?`, `X*` and `X+` make X optional, repeat it any number of times or at least once.
+//   They apply to variables, quoted terminals, classes and groups, written without a space before them.
+//   After a bare terminal they are part of it, which `Cfg::lint` warns about
+// - `(A | B c)` groups alternatives, with the parentheses written against what they hold.
+//   A `(` or `)` on its own is still a terminal, and `Cfg::lint` warns if it has no partner
+// - `[a-z]`, `[0-9_]` and `[^ab]` match any single character in, or not in, the brackets.
+//   A `[` or `]` on its own is still a terminal
+//
+// - `[0.3]` at the end of an alternative is the probability of picking it for its variable,
+//   written with a decimal point. Alternatives without one share what is left equally.
+//   If every alternative has one, they are scaled to add up to 1
+//
+// - `NAME = /regex/` declares a token matched by a regular expression, for scanning raw text.
+//   NAME is then a terminal that the scanner produces for any text the expression matches
+//
+// The EBNF operators are rewritten into plain productions using helper variables named `NAME~1`,
+// `NAME~2` and so on after the rule they appear in
+
+use std::collections::{HashMap, HashSet};
+
+use super::error::{GrammarError, GrammarErrorKind};
+use super::production::{Origin, Production};
+use super::terminal_class::TerminalClass;
+use crate::regex::regex::Regex;
+
+// The rules of a grammar file, in the order they were written
+#[derive(Clone, Debug)]
+pub struct Grammar {
+    pub starting_variable: String,
+    pub productions: Vec<Production>,
+    // The terminal classes used, keyed by how they were written
+    pub classes: HashMap<String, TerminalClass>,
+    // Tokens declared with `NAME = /regex/`, in the order they were declared
+    pub tokens: Vec<(String, String)>,
+    // The line each production was written on
+    pub lines: Vec<usize>,
+    // Terminals written in quotes, which are never mistaken for variables
+    pub quoted: HashSet<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Quoted(String),
+    // A terminal class as written, brackets included
+    Class(String),
+    // The pattern of a token declaration, without the slashes
+    Regex(String),
+    // The weight of an alternative as written, without the brackets
+    Weight(String),
+    Arrow,
+    Pipe,
+    Epsilon,
+    Directive(String),
+    Open,
+    Close,
+    // One of `?`, `*` or `+`
+    Postfix(char),
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    line: usize,
+    column: usize,
+    // The column just past the end of the token
+    end: usize,
+    starts_line: bool,
+    kind: TokenKind,
+}
+
+// A symbol on the right hand side along with where it was written
+#[derive(Clone, Debug)]
+struct Symbol {
+    line: usize,
+    column: usize,
+    name: String,
+    quoted: bool,
+}
+
+// The weight given to an alternative along with where it was written
+#[derive(Clone, Copy, Debug)]
+struct Weight {
+    line: usize,
+    column: usize,
+    value: f64,
+}
+
+// One alternative of a rule, before the EBNF operators are rewritten
+struct Alternative {
+    line: usize,
+    items: Vec<Item>,
+    weight: Option<Weight>,
+}
+
+enum Item {
+    Symbol(Symbol),
+    Group(Vec<Alternative>),
+    // The item, the operator and the line the operator is on
+    Repeat(Box<Item>, char, usize),
+}
+
+// Rewrites EBNF rules into plain productions
+struct Desugarer {
+    productions: Vec<Production>,
+    // The line and the symbols of each production
+    positions: Vec<(usize, Vec<Symbol>)>,
+    // The weight written for each production, if any
+    weights: Vec<Option<Weight>>,
+    // Every name written in the grammar, so helpers never reuse one
+    taken: HashSet<String>,
+}
+
+impl Desugarer {
+    fn add_rule(&mut self, name: &str, alternatives: Vec<Alternative>) {
+        for alternative in alternatives {
+            let mut symbols = Vec::new();
+            let value: Vec<String> = alternative
+                .items
+                .into_iter()
+                .map(|item| self.symbol_for(name, item, &mut symbols))
+                .collect();
+
+            self.add_production(name, value, alternative.line, symbols, alternative.weight);
+        }
+    }
+
+    fn add_production(
+        &mut self,
+        name: &str,
+        value: Vec<String>,
+        line: usize,
+        symbols: Vec<Symbol>,
+        weight: Option<Weight>,
+    ) {
+        let value = if value.is_empty() {
+            vec!["#".to_string()]
+        } else {
+            value
+        };
+
+        self.productions.push(Production {
+            symbol: name.to_string(),
+            value,
+            origin: Some(Origin {
+                index: self.productions.len(),
+                units: vec![],
+                removed: vec![],
+            }),
+            // Set once every production is known, see `assign_weights`
+            weight: 1.0,
+        });
+        self.positions.push((line, symbols));
+        self.weights.push(weight);
+    }
+
+    // The symbol that stands for the item, adding helper productions as needed
+    fn symbol_for(&mut self, rule: &str, item: Item, symbols: &mut Vec<Symbol>) -> String {
+        match item {
+            Item::Symbol(symbol) => {
+                symbols.push(symbol.clone());
+                symbol.name
+            }
+            Item::Group(alternatives) => {
+                let helper = self.fresh_name(rule);
+                self.add_rule(&helper, alternatives);
+                helper
+            }
+            Item::Repeat(item, op, line) => {
+                let repeated = self.symbol_for(rule, *item, symbols);
+                let helper = self.fresh_name(rule);
+
+                let (first, second) = match op {
+                    // X? -> X | #
+                    '?' => (vec![repeated], vec![]),
+                    // X* -> X X* | #
+                    '*' => (vec![repeated, helper.clone()], vec![]),
+                    // X+ -> X X+ | X
+                    _ => (vec![repeated.clone(), helper.clone()], vec![repeated]),
+                };
+
+                self.add_production(&helper, first, line, vec![], None);
+                self.add_production(&helper, second, line, vec![], None);
+                helper
+            }
+        }
+    }
+
+    fn fresh_name(&mut self, rule: &str) -> String {
+        let name = (1..)
+            .map(|n| format!("{}~{}", rule, n))
+            .find(|name| !self.taken.contains(name))
+            .unwrap();
+
+        self.taken.insert(name.clone());
+        name
+    }
+}
+
+// Variables are named in capitals, like `S` or `ATLEAST_ONE_LITERAL`
+pub fn looks_like_variable(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == '`')
+}
+
+// Syntax errors are always reported. When strict, so are undefined and duplicate rules
+pub fn read_grammar(input: &str, strict: bool) -> Result<Grammar, GrammarError> {
+    let tokens = tokenize(input)?;
+
+    let taken = tokens
+        .iter()
+        .filter_map(|t| match &t.kind {
+            TokenKind::Word(name) | TokenKind::Quoted(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let classes: HashMap<String, TerminalClass> = tokens
+        .iter()
+        .filter_map(|t| match &t.kind {
+            TokenKind::Class(text) => Some((text.clone(), TerminalClass::parse(text)?)),
+            _ => None,
+        })
+        .collect();
+    let mut desugarer = Desugarer {
+        productions: vec![],
+        positions: vec![],
+        weights: vec![],
+        taken,
+    };
+    let mut start: Option<(Token, String)> = None;
+    let mut first_rule: Option<String> = None;
+    let mut token_definitions: Vec<(Token, String, String)> = Vec::new();
+
+    for statement in statements(&tokens) {
+        let first = &statement[0];
+
+        if statement
+            .get(1)
+            .is_some_and(|t| t.kind == TokenKind::Word("=".to_string()))
+        {
+            let (name, pattern) = read_token_definition(statement)?;
+            token_definitions.push((first.clone(), name, pattern));
+            continue;
+        }
+
+        if let TokenKind::Directive(directive) = &first.kind {
+            let name = read_directive(directive, statement)?;
+            if start.is_some() {
+                let kind = GrammarErrorKind::UnexpectedToken(format!("%{}", directive));
+                return Err(error_at(first, kind));
+            }
+
+            start = Some((first.clone(), name));
+            continue;
+        }
+
+        let alternatives = read_rule(statement)?;
+        let TokenKind::Word(name) = &first.kind else {
+            unreachable!("read_rule checks the name");
+        };
+
+        first_rule.get_or_insert(name.clone());
+        desugarer.add_rule(name, alternatives);
+    }
+
+    let Desugarer {
+        mut productions,
+        positions,
+        weights,
+        ..
+    } = desugarer;
+
+    let Some(first_rule) = first_rule else {
+        return Err(GrammarError {
+            line: 1,
+            column: 1,
+            kind: GrammarErrorKind::EmptyGrammar,
+        });
+    };
+
+    assign_weights(&mut productions, &weights)?;
+
+    let defined: HashSet<&str> = productions.iter().map(|p| p.symbol.as_str()).collect();
+
+    // A token can only be declared once, and not also have rules
+    let mut token_names: HashSet<&str> = HashSet::new();
+    for (token, name, pattern) in token_definitions.iter() {
+        if defined.contains(name.as_str()) || !token_names.insert(name.as_str()) {
+            let rule = format!("{} = /{}/", name, pattern);
+            return Err(error_at(token, GrammarErrorKind::DuplicateRule(rule)));
+        }
+    }
+
+    // A quoted terminal must not be mistaken for a variable, a class or epsilon
+    for (_, symbols) in positions.iter() {
+        for symbol in symbols.iter().filter(|s| s.quoted) {
+            if symbol.name.is_empty()
+                || symbol.name == "#"
+                || defined.contains(symbol.name.as_str())
+                || classes.contains_key(&symbol.name)
+            {
+                return Err(GrammarError {
+                    line: symbol.line,
+                    column: symbol.column,
+                    kind: GrammarErrorKind::ReservedTerminal(symbol.name.clone()),
+                });
+            }
+        }
+    }
+
+    let starting_variable = match &start {
+        Some((token, name)) => {
+            if strict && !defined.contains(name.as_str()) {
+                let kind = GrammarErrorKind::UndefinedSymbol(name.clone());
+                return Err(error_at(token, kind));
+            }
+            name.clone()
+        }
+        None => first_rule,
+    };
+
+    if strict {
+        let mut seen: HashSet<&Production> = HashSet::new();
+
+        for (prod, (line, symbols)) in productions.iter().zip(positions.iter()) {
+            let error = |column: usize, kind: GrammarErrorKind| GrammarError {
+                line: *line,
+                column,
+                kind,
+            };
+            let first_column = symbols.first().map(|s| s.column).unwrap_or(1);
+
+            if !seen.insert(prod) {
+                let rule = format!("{} -> {}", prod.symbol, prod.value.join(" "));
+                return Err(error(first_column, GrammarErrorKind::DuplicateRule(rule)));
+            }
+
+            for symbol in symbols.iter().filter(|s| !s.quoted) {
+                if looks_like_variable(&symbol.name)
+                    && !defined.contains(symbol.name.as_str())
+                    && !token_names.contains(symbol.name.as_str())
+                {
+                    let kind = GrammarErrorKind::UndefinedSymbol(symbol.name.clone());
+                    return Err(error(symbol.column, kind));
+                }
+            }
+        }
+    }
+
+    let tokens = token_definitions
+        .into_iter()
+        .map(|(_, name, pattern)| (name, pattern))
+        .collect();
+    let lines = positions.iter().map(|(line, _)| *line).collect();
+    let quoted = positions
+        .iter()
+        .flat_map(|(_, symbols)| symbols.iter().filter(|s| s.quoted))
+        .map(|s| s.name.clone())
+        .collect();
+
+    Ok(Grammar {
+        starting_variable,
+        productions,
+        classes,
+        tokens,
+        lines,
+        quoted,
+    })
+}
+
+// Turns the weights as written into the probability of each production.
+// Weights of a variable must not add up to more than 1, and productions without one
+// share what is left. If all of them have one, they are scaled to add up to 1
+fn assign_weights(
+    productions: &mut [Production],
+    weights: &[Option<Weight>],
+) -> Result<(), GrammarError> {
+    let mut variables: Vec<&str> = Vec::new();
+    for prod in productions.iter() {
+        if !variables.contains(&prod.symbol.as_str()) {
+            variables.push(&prod.symbol);
+        }
+    }
+
+    let mut assigned = vec![0.0; productions.len()];
+    for variable in variables {
+        let indices: Vec<usize> = (0..productions.len())
+            .filter(|i| productions[*i].symbol == variable)
+            .collect();
+
+        let mut total = 0.0;
+        for weight in indices.iter().filter_map(|i| weights[*i]) {
+            total += weight.value;
+
+            // Leave some room for rounding, 0.1 + 0.2 + 0.7 is a little over 1
+            if total > 1.0 + 1e-9 {
+                return Err(GrammarError {
+                    line: weight.line,
+                    column: weight.column,
+                    kind: GrammarErrorKind::WeightsOverOne(variable.to_string()),
+                });
+            }
+        }
+
+        let unweighted = indices.iter().filter(|i| weights[**i].is_none()).count();
+        let weighted = indices.len() - unweighted;
+        for i in indices {
+            assigned[i] = match weights[i] {
+                // Nothing to scale, so every alternative is as likely
+                Some(_) if unweighted == 0 && total == 0.0 => 1.0 / weighted as f64,
+                Some(weight) if unweighted == 0 => weight.value / total,
+                Some(weight) => weight.value,
+                None => (1.0 - total).max(0.0) / unweighted as f64,
+            };
+        }
+    }
+
+    for (prod, weight) in productions.iter_mut().zip(assigned) {
+        prod.weight = weight;
+    }
+
+    Ok(())
+}
+
+fn error_at(token: &Token, kind: GrammarErrorKind) -> GrammarError {
+    GrammarError {
+        line: token.line,
+        column: token.column,
+        kind,
+    }
+}
+
+// `NAME = /regex/`, returning the name and the pattern
+fn read_token_definition(statement: &[Token]) -> Result<(String, String), GrammarError> {
+    let first = &statement[0];
+    let TokenKind::Word(name) = &first.kind else {
+        return Err(error_at(first, GrammarErrorKind::MissingName));
+    };
+
+    let pattern = match statement.get(2).map(|t| &t.kind) {
+        Some(TokenKind::Regex(pattern)) => pattern,
+        _ => {
+            let kind =
+                GrammarErrorKind::InvalidToken(format!("expected /regex/ after `{} =`", name));
+            return Err(error_at(&statement[1], kind));
+        }
+    };
+
+    if let Some(extra) = statement.get(3) {
+        return Err(error_at(extra, unexpected(&extra.kind)));
+    }
+
+    if let Err(e) = Regex::try_new(pattern) {
+        let kind = GrammarErrorKind::InvalidToken(e);
+        return Err(error_at(&statement[2], kind));
+    }
+
+    Ok((name.clone(), pattern.clone()))
+}
+
+// `%start NAME`
+fn read_directive(directive: &str, statement: &[Token]) -> Result<String, GrammarError> {
+    let first = &statement[0];
+    if directive != "start" {
+        let kind = GrammarErrorKind::UnknownDirective(directive.to_string());
+        return Err(error_at(first, kind));
+    }
+
+    match statement.get(1) {
+        Some(Token {
+            kind: TokenKind::Word(name),
+            ..
+        }) => {
+            if let Some(extra) = statement.get(2) {
+                return Err(error_at(extra, unexpected(&extra.kind)));
+            }
+            Ok(name.clone())
+        }
+        Some(token) => Err(error_at(token, GrammarErrorKind::MissingName)),
+        None => Err(GrammarError {
+            line: first.line,
+            column: first.end,
+            kind: GrammarErrorKind::MissingName,
+        }),
+    }
+}
+
+// `NAME -> alternative | alternative ...`
+fn read_rule(statement: &[Token]) -> Result<Vec<Alternative>, GrammarError> {
+    let first = &statement[0];
+    match &first.kind {
+        TokenKind::Word(_) => {}
+        TokenKind::Arrow => return Err(error_at(first, GrammarErrorKind::MissingName)),
+        kind => return Err(error_at(first, unexpected(kind))),
+    }
+
+    match statement.get(1) {
+        Some(Token {
+            kind: TokenKind::Arrow,
+            ..
+        }) => {}
+        Some(token) => return Err(error_at(token, GrammarErrorKind::MissingArrow)),
+        None => {
+            return Err(GrammarError {
+                line: first.line,
+                column: first.end,
+                kind: GrammarErrorKind::MissingArrow,
+            })
+        }
+    }
+
+    let mut pos = 2;
+
+    // The first alternative may start on the next line:
+    //     A ->
+    //       | a
+    if statement
+        .get(pos)
+        .is_some_and(|t| t.kind == TokenKind::Pipe && t.starts_line)
+    {
+        pos += 1;
+    }
+
+    let before = &statement[pos - 1];
+    read_alternatives(statement, &mut pos, before, None)
+}
+
+// Reads alternatives separated by `|` until the end of the statement,
+// or until the `)` matching `open` if this is a group
+fn read_alternatives(
+    statement: &[Token],
+    pos: &mut usize,
+    before: &Token,
+    open: Option<&Token>,
+) -> Result<Vec<Alternative>, GrammarError> {
+    // Each alternative along with the token just before it, where an empty one is reported,
+    // whether it had an epsilon and its weight
+    let mut alternatives: Vec<(&Token, Vec<Item>, bool, Option<Weight>)> =
+        vec![(before, vec![], false, None)];
+
+    loop {
+        let Some(token) = statement.get(*pos) else {
+            if let Some(open) = open {
+                return Err(error_at(open, GrammarErrorKind::UnclosedGroup));
+            }
+            break;
+        };
+        *pos += 1;
+
+        let (_, items, saw_epsilon, weight) = alternatives.last_mut().unwrap();
+
+        // The weight ends the alternative
+        if weight.is_some() && !matches!(token.kind, TokenKind::Pipe | TokenKind::Close) {
+            return Err(error_at(token, unexpected(&token.kind)));
+        }
+
+        match &token.kind {
+            TokenKind::Pipe => alternatives.push((token, vec![], false, None)),
+            TokenKind::Close if open.is_some() => break,
+            TokenKind::Open => {
+                let group = read_alternatives(statement, pos, token, Some(token))?;
+                items.push(Item::Group(group));
+            }
+            TokenKind::Word(name) | TokenKind::Quoted(name) | TokenKind::Class(name) => {
+                items.push(Item::Symbol(Symbol {
+                    line: token.line,
+                    column: token.column,
+                    name: name.clone(),
+                    quoted: matches!(token.kind, TokenKind::Quoted(_)),
+                }))
+            }
+            // Epsilon next to other symbols adds nothing
+            TokenKind::Epsilon => *saw_epsilon = true,
+            TokenKind::Weight(text) => match text.parse::<f64>() {
+                Ok(value) if (0.0..=1.0).contains(&value) => {
+                    *weight = Some(Weight {
+                        line: token.line,
+                        column: token.column,
+                        value,
+                    })
+                }
+                _ => {
+                    let kind = GrammarErrorKind::InvalidWeight(text.clone());
+                    return Err(error_at(token, kind));
+                }
+            },
+            TokenKind::Postfix(op) => match items.pop() {
+                Some(item) => items.push(Item::Repeat(Box::new(item), *op, token.line)),
+                None => return Err(error_at(token, unexpected(&token.kind))),
+            },
+            kind => return Err(error_at(token, unexpected(kind))),
+        }
+    }
+
+    let mut read = Vec::new();
+    for (separator, items, saw_epsilon, weight) in alternatives {
+        if items.is_empty() && !saw_epsilon {
+            return Err(error_at(separator, GrammarErrorKind::EmptyAlternative));
+        }
+
+        read.push(Alternative {
+            line: separator.line,
+            items,
+            weight,
+        });
+    }
+
+    Ok(read)
+}
+
+fn unexpected(kind: &TokenKind) -> GrammarErrorKind {
+    let text = match kind {
+        TokenKind::Word(word) => word.clone(),
+        TokenKind::Quoted(word) => format!("\"{}\"", word),
+        TokenKind::Class(text) => text.clone(),
+        TokenKind::Regex(pattern) => format!("/{}/", pattern),
+        TokenKind::Weight(text) => format!("[{}]", text),
+        TokenKind::Arrow => "->".to_string(),
+        TokenKind::Pipe => "|".to_string(),
+        TokenKind::Epsilon => "%empty".to_string(),
+        TokenKind::Directive(name) => format!("%{}", name),
+        TokenKind::Open => "(".to_string(),
+        TokenKind::Close => ")".to_string(),
+        TokenKind::Postfix(op) => op.to_string(),
+    };
+
+    GrammarErrorKind::UnexpectedToken(text)
+}
+
+// Groups the tokens into statements. A statement ends at the end of a line,
+// unless the next line starts with `|`
+fn statements(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        if i > start && token.starts_line && token.kind != TokenKind::Pipe {
+            statements.push(&tokens[start..i]);
+            start = i;
+        }
+    }
+
+    if start < tokens.len() {
+        statements.push(&tokens[start..]);
+    }
+
+    statements
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, GrammarError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    // How many groups are open, a `)` only closes one if there is one
+    let mut depth = 0;
+
+    for (line_idx, line) in input.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let line_start = tokens.len();
+        let mut starts_line = true;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+
+            // Comments run to the end of the line
+            if c == '/' && chars.get(i + 1) == Some(&'/') {
+                break;
+            }
+
+            // Written right against the previous token
+            let glued = !starts_line && !chars[i - 1].is_whitespace();
+            let after_repeatable = glued
+                && tokens.last().is_some_and(|t| match &t.kind {
+                    TokenKind::Word(word) => looks_like_variable(word),
+                    TokenKind::Quoted(_) | TokenKind::Class(_) | TokenKind::Close => true,
+                    _ => false,
+                });
+
+            // A pattern only follows `NAME =` at the start of a line
+            let declaring_token = tokens.len() == line_start + 2
+                && matches!(tokens[line_start].kind, TokenKind::Word(_))
+                && tokens[line_start + 1].kind == TokenKind::Word("=".to_string());
+
+            let column = i + 1;
+            let kind = if c == '/' && declaring_token {
+                let (pattern, end) = read_delimited(&chars, i, '/').ok_or(GrammarError {
+                    line: line_idx + 1,
+                    column,
+                    kind: GrammarErrorKind::UnterminatedQuote,
+                })?;
+                i = end;
+                TokenKind::Regex(pattern)
+            } else if c == '(' && chars.get(i + 1).is_some_and(|n| !n.is_whitespace()) {
+                depth += 1;
+                i += 1;
+                TokenKind::Open
+            } else if c == ')' && depth > 0 {
+                depth -= 1;
+                i += 1;
+                TokenKind::Close
+            } else if matches!(c, '?' | '*' | '+') && after_repeatable {
+                i += 1;
+                TokenKind::Postfix(c)
+            } else if let Some(end) = weight_end(&chars, i) {
+                let text: String = chars[i + 1..end - 1].iter().collect();
+                i = end;
+                TokenKind::Weight(text)
+            } else if let Some(end) = class_end(&chars, i) {
+                let text: String = chars[i..end].iter().collect();
+                if TerminalClass::parse(&text).is_none() {
+                    return Err(GrammarError {
+                        line: line_idx + 1,
+                        column,
+                        kind: GrammarErrorKind::InvalidClass(text),
+                    });
+                }
+                i = end;
+                TokenKind::Class(text)
+            } else if c == '"' {
+                let (quoted, end) = read_delimited(&chars, i, '"').ok_or(GrammarError {
+                    line: line_idx + 1,
+                    column,
+                    kind: GrammarErrorKind::UnterminatedQuote,
+                })?;
+                i = end;
+                TokenKind::Quoted(quoted)
+            } else {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !(chars[i] == ')' && depth > 0)
+                {
+                    i += 1;
+                }
+
+                // Leave the operators after a variable to be read on their own
+                let mut end = i;
+                while end > start && matches!(chars[end - 1], '?' | '*' | '+') {
+                    end -= 1;
+                }
+                let name: String = chars[start..end].iter().collect();
+                if end > start && end < i && looks_like_variable(&name) {
+                    i = end;
+                }
+
+                let word: String = chars[start..i].iter().collect();
+                match word.as_str() {
+                    "->" => TokenKind::Arrow,
+                    "|" => TokenKind::Pipe,
+                    "#" | "%empty" => TokenKind::Epsilon,
+                    _ => match word.strip_prefix('%') {
+                        Some(directive) if !directive.is_empty() => {
+                            TokenKind::Directive(directive.to_string())
+                        }
+                        _ => TokenKind::Word(word),
+                    },
+                }
+            };
+
+            tokens.push(Token {
+                line: line_idx + 1,
+                column,
+                end: i + 1,
+                starts_line,
+                kind,
+            });
+            starts_line = false;
+        }
+    }
+
+    Ok(tokens)
+}
+
+// If a weight like `[0.3]` starts at `start`, the index just past its closing `]`.
+// It is a number with a decimal point, which tells it apart from a class like `[01]`
+fn weight_end(chars: &[char], start: usize) -> Option<usize> {
+    if chars[start] != '[' {
+        return None;
+    }
+
+    let close = start + chars[start..].iter().position(|c| *c == ']')?;
+    let inner = &chars[start + 1..close];
+    let is_number = inner.iter().all(|c| c.is_ascii_digit() || *c == '.')
+        && inner.iter().filter(|c| **c == '.').count() == 1
+        && inner.iter().any(|c| c.is_ascii_digit());
+
+    is_number.then_some(close + 1)
+}
+
+// If a terminal class starts at `start`, the index just past its closing `]`.
+// The brackets must hold something and there can be no spaces inside
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    if chars[start] != '['
+        || chars
+            .get(start + 1)
+            .is_none_or(|c| c.is_whitespace() || *c == ']')
+    {
+        return None;
+    }
+
+    let mut i = start + 1;
+    while i < chars.len() && !chars[i].is_whitespace() {
+        match chars[i] {
+            ']' => return Some(i + 1),
+            '\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+// Reads a quoted terminal or a token pattern starting at the opening delimiter.
+// Returns the text and the index just past the closing delimiter
+fn read_delimited(chars: &[char], start: usize, delimiter: char) -> Option<(String, usize)> {
+    let mut quoted = String::new();
+    let mut i = start + 1;
+
+    while i < chars.len() {
+        match chars[i] {
+            c if c == delimiter => return Some((quoted, i + 1)),
+            '\\' if i + 1 < chars.len() => {
+                quoted.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                quoted.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    None
+}
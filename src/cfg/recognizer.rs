@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+use std::thread;
+
+use super::production::Production;
+use super::symbol_table::SymbolTable;
+use super::terminal_class::{matches_terminal, TerminalClass};
+
+// Fewer cells than this on a diagonal are not worth starting a thread for
+const MIN_CELLS_PER_THREAD: usize = 16;
+
+// The productions of a grammar in CNF with their variables numbered densely,
+// as both `Recognizer` and `Valiant` look them up
+#[derive(Clone, Debug, Default)]
+pub struct RuleIndex {
+    // The id of the starting variable, None if it has no productions left
+    pub start: Option<usize>,
+    // The number of variables
+    pub variables: usize,
+    // A -> a, as (a, A)
+    pub terminal_rules: Vec<(String, usize)>,
+    // A -> B C, as (A, B, C), each rule once
+    pub binary_rules: Vec<(usize, usize, usize)>,
+}
+
+impl RuleIndex {
+    pub fn new(productions: &[Production], starting_variable: &str) -> RuleIndex {
+        let mut variables = SymbolTable::new();
+        productions.iter().for_each(|p| {
+            variables.intern(&p.symbol);
+        });
+
+        let mut terminal_rules = Vec::new();
+        let mut binary_rules = Vec::new();
+        let mut seen = HashSet::new();
+        for prod in productions.iter() {
+            let a = variables.id(&prod.symbol).unwrap();
+            match prod.value.as_slice() {
+                [terminal] => terminal_rules.push((terminal.clone(), a)),
+                [left, right] => {
+                    // A side without productions can never be filled in
+                    if let (Some(b), Some(c)) = (variables.id(left), variables.id(right)) {
+                        if seen.insert((a, b, c)) {
+                            binary_rules.push((a, b, c));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        RuleIndex {
+            start: variables.id(starting_variable),
+            variables: variables.len(),
+            terminal_rules,
+            binary_rules,
+        }
+    }
+}
+
+// CYK over a grammar in CNF, for when only the answer is needed.
+// Variables are numbered densely and each cell of the chart is a bitset over them,
+// so a cell takes one bit per variable rather than a bool per production.
+// Binary rules are looked up by their left child, so a split only visits the rules
+// that can start with a variable already found on its left side
+#[derive(Clone, Debug, Default)]
+pub struct Recognizer {
+    // The id of the starting variable, None if it has no productions left
+    start: Option<usize>,
+    // A -> a, as (a, A)
+    terminal_rules: Vec<(String, usize)>,
+    // For each B, the rules A -> B C as (C, A)
+    by_left: Vec<Vec<(usize, usize)>>,
+    classes: HashMap<String, TerminalClass>,
+    // The number of u64 words in a cell
+    words: usize,
+}
+
+impl Recognizer {
+    pub fn new(
+        productions: &[Production],
+        classes: &HashMap<String, TerminalClass>,
+        starting_variable: &str,
+    ) -> Recognizer {
+        let rules = RuleIndex::new(productions, starting_variable);
+
+        let mut by_left = vec![Vec::new(); rules.variables];
+        for (a, b, c) in rules.binary_rules {
+            by_left[b].push((c, a));
+        }
+
+        Recognizer {
+            start: rules.start,
+            words: rules.variables.div_ceil(64),
+            terminal_rules: rules.terminal_rules,
+            by_left,
+            classes: classes.clone(),
+        }
+    }
+
+    pub fn recognize(&self, input: &[&str]) -> bool {
+        self.recognize_with_threads(input, 1)
+    }
+
+    // Fills the cells of each diagonal on up to `threads` threads. The cells of a diagonal
+    // only read shorter spans, so they can all be worked out at once
+    pub fn recognize_with_threads(&self, input: &[&str], threads: usize) -> bool {
+        let n = input.len();
+        let Some(start) = self.start else {
+            return false;
+        };
+        if n == 0 {
+            return false;
+        }
+
+        let w = self.words;
+        // The cell for the span of length l + 1 starting at s is at (l * n + s) * w
+        let mut chart = vec![0u64; n * n * w];
+
+        // The same token always gives the same cell, so each one is only matched once
+        let mut seen: HashMap<&str, Vec<u64>> = HashMap::new();
+        for (s, token) in input.iter().enumerate() {
+            let bits = seen.entry(token).or_insert_with(|| {
+                let mut bits = vec![0u64; w];
+                for (terminal, a) in self.terminal_rules.iter() {
+                    if matches_terminal(&self.classes, terminal, token) {
+                        bits[a / 64] |= 1 << (a % 64);
+                    }
+                }
+                bits
+            });
+            chart[s * w..(s + 1) * w].copy_from_slice(bits);
+        }
+
+        // Length of span
+        for l in 1..n {
+            let (shorter, rest) = chart.split_at_mut(l * n * w);
+            let shorter = &*shorter;
+            let diagonal = &mut rest[..(n - l) * w];
+
+            // Each thread takes a run of starts, as long as there are enough to share
+            let per_thread = (n - l).div_ceil(threads.max(1)).max(MIN_CELLS_PER_THREAD);
+            if per_thread >= n - l {
+                for (s, cell) in diagonal.chunks_mut(w).enumerate() {
+                    self.fill_cell(shorter, cell, n, l, s);
+                }
+                continue;
+            }
+
+            thread::scope(|scope| {
+                for (i, run) in diagonal.chunks_mut(per_thread * w).enumerate() {
+                    scope.spawn(move || {
+                        for (j, cell) in run.chunks_mut(w).enumerate() {
+                            self.fill_cell(shorter, cell, n, l, i * per_thread + j);
+                        }
+                    });
+                }
+            });
+        }
+
+        chart[((n - 1) * n) * w + start / 64] & (1 << (start % 64)) != 0
+    }
+
+    // Works out the cell for the span of length l + 1 starting at s from the shorter spans
+    fn fill_cell(&self, shorter: &[u64], cell: &mut [u64], n: usize, l: usize, s: usize) {
+        let w = self.words;
+
+        // Partition of span
+        for p in 0..l {
+            let left = (p * n + s) * w;
+            let right = ((l - p - 1) * n + s + p + 1) * w;
+            for word in 0..w {
+                let mut bits = shorter[left + word];
+                while bits != 0 {
+                    let b = word * 64 + bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+
+                    for (c, a) in self.by_left[b].iter() {
+                        if shorter[right + c / 64] & (1 << (c % 64)) != 0 {
+                            cell[a / 64] |= 1 << (a % 64);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
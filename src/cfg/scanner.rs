@@ -0,0 +1,97 @@
+use std::collections::{HashMap, HashSet};
+
+use super::production::Production;
+use super::terminal_class::TerminalClass;
+use crate::regex::regex::Regex;
+
+// Splits raw text into the terminals of a grammar, skipping whitespace between them.
+// At each point the longest match wins. On a tie, terminals written in the grammar
+// and classes win over declared tokens, and earlier tokens win over later ones
+pub struct Scanner<'a> {
+    // Terminals written in the grammar, matched exactly
+    literals: Vec<&'a str>,
+    classes: &'a HashMap<String, TerminalClass>,
+    // Declared tokens in the order they were declared
+    tokens: Vec<(&'a str, Regex)>,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(
+        productions: &'a [Production],
+        classes: &'a HashMap<String, TerminalClass>,
+        tokens: &'a [(String, String)],
+    ) -> Scanner<'a> {
+        let variables: HashSet<&str> = productions.iter().map(|p| p.symbol.as_str()).collect();
+        let token_names: HashSet<&str> = tokens.iter().map(|(name, _)| name.as_str()).collect();
+
+        let mut literals: Vec<&str> = productions
+            .iter()
+            .filter(|p| !p.is_null())
+            .flat_map(|p| p.value.iter().map(|s| s.as_str()))
+            .filter(|s| {
+                !variables.contains(s) && !classes.contains_key(*s) && !token_names.contains(s)
+            })
+            .collect();
+        literals.sort();
+        literals.dedup();
+
+        // The patterns were checked when the grammar was read
+        let tokens = tokens
+            .iter()
+            .map(|(name, pattern)| (name.as_str(), Regex::new(pattern)))
+            .collect();
+
+        Scanner {
+            literals,
+            classes,
+            tokens,
+        }
+    }
+
+    // The terminals the text is made of. Declared tokens come out as their name.
+    // If nothing matches somewhere, returns the byte offset where scanning stopped
+    pub fn scan(&self, text: &str) -> Result<Vec<String>, usize> {
+        let mut terminals = Vec::new();
+        let mut pos = 0;
+
+        while let Some(c) = text[pos..].chars().next() {
+            if c.is_whitespace() {
+                pos += c.len_utf8();
+                continue;
+            }
+
+            let rest = &text[pos..];
+
+            // Everything that matches here, in priority order
+            let mut matches: Vec<(usize, String)> = Vec::new();
+            for literal in self.literals.iter() {
+                if rest.starts_with(literal) {
+                    matches.push((literal.len(), literal.to_string()));
+                }
+            }
+
+            if self.classes.values().any(|class| class.contains(c)) {
+                matches.push((c.len_utf8(), c.to_string()));
+            }
+
+            for (name, regex) in self.tokens.iter() {
+                match regex.match_len(rest) {
+                    Some(len) if len > 0 => matches.push((len, name.to_string())),
+                    _ => {}
+                }
+            }
+
+            // The longest match, taking the first on a tie
+            let longest = matches.iter().map(|(len, _)| *len).max().ok_or(pos)?;
+            let (len, terminal) = matches
+                .into_iter()
+                .find(|(len, _)| *len == longest)
+                .unwrap();
+
+            pos += len;
+            terminals.push(terminal);
+        }
+
+        Ok(terminals)
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use super::production::Production;
+
+// The names of a grammar's symbols, each with a dense id in the order it was first seen.
+// New symbols made up during CNF conversion get a name that no other symbol has,
+// so they can never be confused with one written in the grammar
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+#[allow(dead_code)]
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    // Every variable and terminal used in the productions
+    pub fn from_productions(productions: &[Production]) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        for prod in productions.iter() {
+            table.intern(&prod.symbol);
+            prod.value.iter().for_each(|s| {
+                table.intern(s);
+            });
+        }
+        table
+    }
+
+    // The id of the name, adding it if it is new
+    pub fn intern(&mut self, name: &str) -> usize {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    // The name as written, or as made up by `fresh`
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    // A new symbol named `hint` if that is free, otherwise `hint` with the first number
+    // that makes it free. The name is taken from then on
+    pub fn fresh(&mut self, hint: &str) -> usize {
+        let name = if self.ids.contains_key(hint) {
+            (1..)
+                .map(|n| format!("{}{}", hint, n))
+                .find(|name| !self.ids.contains_key(name))
+                .unwrap()
+        } else {
+            hint.to_string()
+        };
+
+        self.intern(&name)
+    }
+
+    // Like `fresh`, returning the name
+    pub fn fresh_name(&mut self, hint: &str) -> String {
+        let id = self.fresh(hint);
+        self.names[id].clone()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn fresh_names_never_collide() {
+    let mut table = SymbolTable::new();
+    let ab = table.intern("AB");
+    table.intern("AB1");
+
+    let fresh = table.fresh("AB");
+    assert_ne!(fresh, ab);
+    assert_eq!(table.name(fresh), "AB2");
+    assert_eq!(table.fresh_name("AB"), "AB3");
+    assert_eq!(table.fresh_name("C`"), "C`");
+    assert_eq!(table.id("AB"), Some(ab));
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+// A set of single characters written as one terminal, like `[a-z]`, `[0-9_]` or `[^ab]`.
+// `\` escapes `]`, `-`, `^` and itself inside the brackets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TerminalClass {
+    negated: bool,
+    // Inclusive ranges, a single character is a range of one
+    ranges: Vec<(char, char)>,
+}
+
+#[allow(dead_code)]
+impl TerminalClass {
+    // Parses the class as written, brackets included.
+    // Returns None if it is not a class, or if a range runs backwards
+    pub fn parse(text: &str) -> Option<TerminalClass> {
+        let inner = text.strip_prefix('[')?.strip_suffix(']')?;
+        let mut chars: Vec<(char, bool)> = Vec::new();
+
+        // Read the characters, remembering which ones were escaped
+        let mut iter = inner.chars();
+        while let Some(c) = iter.next() {
+            if c == '\\' {
+                chars.push((iter.next()?, true));
+            } else {
+                chars.push((c, false));
+            }
+        }
+
+        let negated = chars.first() == Some(&('^', false));
+        if negated {
+            chars.remove(0);
+        }
+
+        if chars.is_empty() {
+            return None;
+        }
+
+        let mut ranges = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let (from, _) = chars[i];
+
+            // A `-` between two characters makes a range, anywhere else it is itself
+            match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some(('-', false)), Some((to, _))) => {
+                    if *to < from {
+                        return None;
+                    }
+                    ranges.push((from, *to));
+                    i += 3;
+                }
+                _ => {
+                    ranges.push((from, from));
+                    i += 1;
+                }
+            }
+        }
+
+        Some(TerminalClass { negated, ranges })
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        let in_ranges = self.ranges.iter().any(|(from, to)| *from <= c && c <= *to);
+        in_ranges != self.negated
+    }
+
+    // True if the token is a single character in the class
+    pub fn matches(&self, token: &str) -> bool {
+        let mut chars = token.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self.contains(c),
+            _ => false,
+        }
+    }
+
+    // Every character in the class, in order.
+    // A negated class only counts printable ASCII characters as members
+    pub fn members(&self) -> Vec<char> {
+        if self.negated {
+            return (' '..='~').filter(|c| self.contains(*c)).collect();
+        }
+
+        let mut members: Vec<char> = self
+            .ranges
+            .iter()
+            .flat_map(|(from, to)| *from..=*to)
+            .collect();
+        members.sort();
+        members.dedup();
+        members
+    }
+}
+
+// True if the token is in the terminal's class, or is the terminal itself if it is not one.
+// The written text of a class is not one of its members
+pub fn matches_terminal(
+    classes: &HashMap<String, TerminalClass>,
+    terminal: &str,
+    token: &str,
+) -> bool {
+    classes
+        .get(terminal)
+        .map_or(terminal == token, |class| class.matches(token))
+}
+
+#[cfg(test)]
+#[test]
+fn test_terminal_class() {
+    let class = TerminalClass::parse("[a-cx\\-]").unwrap();
+    assert!(class.matches("b"));
+    assert!(class.matches("-"));
+    assert!(!class.matches("d"));
+    assert!(!class.matches("ab"));
+    assert_eq!(class.members(), vec!['-', 'a', 'b', 'c', 'x']);
+
+    let negated = TerminalClass::parse("[^ -}]").unwrap();
+    assert!(negated.matches("~"));
+    assert!(!negated.matches("a"));
+    assert_eq!(negated.members(), vec!['~']);
+
+    assert!(TerminalClass::parse("[z-a]").is_none());
+    assert!(TerminalClass::parse("[]").is_none());
+    assert!(TerminalClass::parse("a-z").is_none());
+}
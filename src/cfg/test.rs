@@ -1,4 +1,6 @@
-use super::cfg::Cfg;
+use super::cfg::{Backend, Cfg};
+use super::error::{GrammarError, GrammarErrorKind};
+use super::parse_tree::ParseTree;
 
 macro_rules! test {
     ($name:ident, $script:expr, $input:expr, $expected:expr) => {
@@ -10,7 +12,6 @@ macro_rules! test {
     };
 }
 
-#[allow(unused_macros)]
 macro_rules! split_space {
     ($input:expr) => {
         $input.trim().split(" ").collect()
@@ -83,3 +84,1498 @@ fn any_zeroes_then_ones() {
     assert!(cfg.test(split!("000000111")));
     assert!(!cfg.test(split!("00001110")));
 }
+
+#[test]
+fn parse_rejects_what_test_rejects() {
+    let cfg = Cfg::new("S -> 0 S 1 | #");
+    assert!(cfg.parse(split!("0011")).is_some());
+    assert!(cfg.parse(split!("0010")).is_none());
+    assert!(cfg.parse(split!("")).is_none());
+}
+
+#[test]
+fn parse_tree_derives_input() {
+    let cfg = Cfg::new("S -> A B \nA -> a \nB -> b");
+    let tree = cfg.parse(split!("ab")).unwrap();
+    assert_eq!(tree.symbol(), "S");
+    assert_eq!(tree.leaves(), vec!["a", "b"]);
+
+    let ParseTree::Node { children, .. } = tree else {
+        panic!("Expected the root to be a node");
+    };
+    assert_eq!(children[0].symbol(), "A");
+    assert_eq!(children[1].symbol(), "B");
+}
+
+#[test]
+fn parse_tree_follows_nested_rules() {
+    let cfg = Cfg::new("S -> 0 S 1 | #");
+    let input: Vec<&str> = split!("000111");
+    let tree = cfg.parse(input.clone()).unwrap();
+    assert_eq!(tree.leaves(), input);
+}
+
+// Renders a tree as nested `SYMBOL(children)` so it can be compared as a string
+fn shape(tree: &ParseTree) -> String {
+    match tree {
+        ParseTree::Node {
+            production,
+            children,
+        } => {
+            let children: Vec<String> = children.iter().map(shape).collect();
+            format!("{}({})", production.symbol, children.join(" "))
+        }
+        ParseTree::Leaf(terminal) => terminal.clone(),
+    }
+}
+
+#[test]
+fn restore_tree_puts_back_epsilon() {
+    let cfg = Cfg::new("S -> A B \nA -> a | # \nB -> b");
+    let tree = cfg.restore_tree(&cfg.parse(split!("b")).unwrap());
+    assert_eq!(shape(&tree), "S(A(#) B(b))");
+    assert_eq!(tree.leaves(), vec!["b"]);
+}
+
+#[test]
+fn restore_tree_puts_back_unit_productions() {
+    let cfg = Cfg::new("S -> A \nA -> a");
+    let tree = cfg.restore_tree(&cfg.parse(split!("a")).unwrap());
+    assert_eq!(shape(&tree), "S(A(a))");
+}
+
+#[test]
+fn restore_tree_flattens_helpers() {
+    let cfg = Cfg::new("S -> a S b | c");
+    let tree = cfg.restore_tree(&cfg.parse(split!("aacbb")).unwrap());
+    assert_eq!(shape(&tree), "S(a S(a S(c) b) b)");
+}
+
+#[test]
+fn restore_tree_uses_written_productions() {
+    let cfg = Cfg::new("S -> 0 S 1 | #");
+    let tree = cfg.restore_tree(&cfg.parse(split!("01")).unwrap());
+    assert_eq!(shape(&tree), "S(0 S(#) 1)");
+
+    let ParseTree::Node { production, .. } = tree else {
+        panic!("Expected the root to be a node");
+    };
+    assert_eq!(production.value, vec!["0", "S", "1"]);
+}
+
+#[test]
+fn earley_passes_the_basic_tests() {
+    let cases: [(&str, &str, bool); 8] = [
+        ("S -> a", "a", true),
+        ("S -> a", "b", false),
+        ("S -> a | #", "a", true),
+        ("S -> a | #", "", false),
+        ("S -> a \nS -> b", "b", true),
+        ("S -> A B \nA -> a \nB -> b", "ab", true),
+        ("S -> A B \nA -> a \nB -> b", "ba", false),
+        ("S -> A B \nA -> a | # \nB -> b", "b", true),
+    ];
+
+    for (script, input, expected) in cases {
+        let cfg = Cfg::with_backend(script, Backend::Earley);
+        assert_eq!(
+            cfg.test(split!(input)),
+            expected,
+            "{} on {:?}",
+            script,
+            input
+        );
+    }
+}
+
+#[test]
+fn earley_matches_cyk() {
+    let scripts = [
+        "S -> 0 S 1 | #",
+        "S -> 0 S | 1 S | #",
+        "S -> Z O\nZ -> 0 Z | #\nO -> 1 O | #",
+        "S -> A B \nA -> 0 A | # \nB -> 1 B | 1",
+        "S -> A c\nA -> B B\nB -> b | #",
+        // `B` only derives epsilon, so the token `B` is not in the language
+        "S -> a | B\nB -> #",
+    ];
+    let inputs = [
+        "0", "1", "01", "10", "0011", "0101", "000111", "001", "0111", "c", "bc", "bbc", "bbbc",
+        "a", "B",
+    ];
+
+    for script in scripts {
+        let cyk = Cfg::new(script);
+        let earley = Cfg::with_backend(script, Backend::Earley);
+        for input in inputs {
+            assert_eq!(cyk.test(split!(input)), earley.test(split!(input)));
+            assert_eq!(cyk.test_valiant(split!(input)), earley.test(split!(input)));
+        }
+    }
+}
+
+#[test]
+fn earley_parses_written_grammar() {
+    let cfg = Cfg::with_backend("S -> A B \nA -> a | # \nB -> b", Backend::Earley);
+    let tree = cfg.parse(split!("b")).unwrap();
+    assert_eq!(shape(&tree), "S(A(#) B(b))");
+    assert!(cfg.parse(split!("ba")).is_none());
+}
+
+#[test]
+fn earley_handles_left_recursion() {
+    let cfg = Cfg::with_backend("S -> S + S | S * S | n", Backend::Earley);
+    let tree = cfg.parse(split!("n+n*n")).unwrap();
+    assert_eq!(tree.leaves(), vec!["n", "+", "n", "*", "n"]);
+    assert!(!cfg.test(split!("n+*n")));
+}
+
+macro_rules! grammar_error {
+    ($name:ident, $script:expr, $line:expr, $column:expr, $kind:expr) => {
+        #[test]
+        fn $name() {
+            let expected = GrammarError {
+                line: $line,
+                column: $column,
+                kind: $kind,
+            };
+            assert_eq!(Cfg::try_new($script).unwrap_err(), expected);
+        }
+    };
+}
+
+grammar_error!(empty_grammar, " \n\n", 1, 1, GrammarErrorKind::EmptyGrammar);
+grammar_error!(
+    missing_arrow,
+    "S -> A\nA a",
+    2,
+    3,
+    GrammarErrorKind::MissingArrow
+);
+grammar_error!(
+    missing_arrow_at_end,
+    "S",
+    1,
+    2,
+    GrammarErrorKind::MissingArrow
+);
+grammar_error!(missing_name, "-> a", 1, 1, GrammarErrorKind::MissingName);
+grammar_error!(
+    empty_alternative,
+    "S -> a |  | b",
+    1,
+    8,
+    GrammarErrorKind::EmptyAlternative
+);
+grammar_error!(
+    empty_rule,
+    "S -> a\nA ->",
+    2,
+    3,
+    GrammarErrorKind::EmptyAlternative
+);
+grammar_error!(
+    undefined_symbol,
+    "S -> A B\nA -> a",
+    1,
+    8,
+    GrammarErrorKind::UndefinedSymbol("B".to_string())
+);
+grammar_error!(
+    duplicate_rule,
+    "S -> a b\n\nS -> c |  a b",
+    3,
+    11,
+    GrammarErrorKind::DuplicateRule("S -> a b".to_string())
+);
+
+#[test]
+fn loads_with_blank_lines_and_trailing_spaces() {
+    let cfg: Cfg = "\nS -> A B \n\nA -> a  \nB -> b \n".parse().unwrap();
+    assert!(cfg.test(split!("ab")));
+    assert!(!cfg.test(split!("a")));
+}
+
+#[test]
+fn lenient_loading_allows_undefined_symbols() {
+    let cfg = Cfg::new("S -> a B");
+    assert!(cfg.test(split!("aB")));
+}
+
+#[test]
+fn grammar_error_points_at_the_problem() {
+    let error = Cfg::try_new("S -> a\nS b").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 2, column 3: expected `->` after the variable name"
+    );
+}
+
+#[test]
+fn loads_the_example_grammars() {
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    assert!(cfg.test(split!("a(bc)*[xy]")));
+
+    let cfg = Cfg::try_new(include_str!("../../cfg1.txt")).unwrap();
+    assert!(cfg.test(split_space!("{ int a = 1 ; print ( a ) ; }")));
+}
+
+#[test]
+fn grammar_files_allow_comments() {
+    let cfg = Cfg::try_new(
+        "// Balanced zeroes and ones
+        S -> 0 S 1 // wrap
+           | %empty",
+    )
+    .unwrap();
+    assert!(cfg.test(split!("0011")));
+    assert!(!cfg.test(split!("001")));
+}
+
+#[test]
+fn quoted_terminals_can_hold_special_symbols() {
+    let cfg = Cfg::try_new(
+        r#"S -> A "->" A | "|" | "a b" | "\"" | "//"
+A -> a"#,
+    )
+    .unwrap();
+    assert!(cfg.test(vec!["a", "->", "a"]));
+    assert!(cfg.test(vec!["|"]));
+    assert!(cfg.test(vec!["a b"]));
+    assert!(cfg.test(vec!["\""]));
+    assert!(cfg.test(vec!["//"]));
+    assert!(!cfg.test(vec!["a", "a"]));
+}
+
+#[test]
+fn alternatives_continue_over_lines() {
+    let cfg = Cfg::try_new(
+        "S ->
+            | A
+            | B B
+        A -> a
+        B -> b",
+    )
+    .unwrap();
+    assert!(cfg.test(split!("a")));
+    assert!(cfg.test(split!("bb")));
+    assert!(!cfg.test(split!("ab")));
+}
+
+#[test]
+fn start_can_be_declared() {
+    let cfg = Cfg::try_new("A -> a\nS -> A b\n%start S").unwrap();
+    assert!(cfg.test(split!("ab")));
+    assert!(!cfg.test(split!("a")));
+}
+
+#[test]
+fn empty_keyword_is_epsilon() {
+    let cfg = Cfg::try_new("S -> A b\nA -> a | %empty").unwrap();
+    assert!(cfg.test(split!("b")));
+    assert!(cfg.test(split!("ab")));
+}
+
+grammar_error!(
+    unterminated_quote,
+    "S -> \"a",
+    1,
+    6,
+    GrammarErrorKind::UnterminatedQuote
+);
+grammar_error!(
+    unknown_directive,
+    "%begin S\nS -> a",
+    1,
+    1,
+    GrammarErrorKind::UnknownDirective("begin".to_string())
+);
+grammar_error!(
+    undefined_start,
+    "S -> a\n%start T",
+    2,
+    1,
+    GrammarErrorKind::UndefinedSymbol("T".to_string())
+);
+grammar_error!(
+    quoted_epsilon,
+    "S -> a \"#\"",
+    1,
+    8,
+    GrammarErrorKind::ReservedTerminal("#".to_string())
+);
+grammar_error!(
+    stray_arrow,
+    "S -> a -> b",
+    1,
+    8,
+    GrammarErrorKind::UnexpectedToken("->".to_string())
+);
+
+#[test]
+fn ebnf_repetition() {
+    let script = "S -> A+ B* C?\nA -> a\nB -> b\nC -> c";
+    let cfg = Cfg::try_new(script).unwrap();
+    assert!(cfg.test(split!("a")));
+    assert!(cfg.test(split!("aabbbc")));
+    assert!(cfg.test(split!("ac")));
+    assert!(!cfg.test(split!("bc")));
+    assert!(!cfg.test(split!("acc")));
+}
+
+#[test]
+fn ebnf_groups() {
+    let script = "S -> (a | b B)+ \"c\"?\nB -> b";
+    let cfg = Cfg::try_new(script).unwrap();
+    assert!(cfg.test(split!("a")));
+    assert!(cfg.test(split!("abbac")));
+    assert!(!cfg.test(split!("abc")));
+    assert!(!cfg.test(split!("c")));
+}
+
+#[test]
+fn ebnf_shortens_the_example_grammar() {
+    let script = "S -> PATTERN+
+        PATTERN -> LITERAL | PATTERN * | ( PATTERN+ ) | [ LITERAL+ ]
+        LITERAL -> a | b | c";
+    let cfg = Cfg::try_new(script).unwrap();
+    assert!(cfg.test(split!("a(bc)*[ab]")));
+    assert!(cfg.test(split!("(ab)")));
+    assert!(!cfg.test(split!("a(bc")));
+    assert!(!cfg.test(split!("[]")));
+}
+
+#[test]
+fn ebnf_operators_need_something_to_repeat() {
+    // A lowercase terminal keeps its operator, and a lone `*` is a terminal
+    let cfg = Cfg::try_new("S -> a* | b *").unwrap();
+    assert!(cfg.test(vec!["a*"]));
+    assert!(cfg.test(vec!["b", "*"]));
+    assert!(!cfg.test(vec!["a", "a"]));
+}
+
+#[test]
+fn ebnf_helpers_do_not_reuse_names() {
+    let script = "S -> A* S~1\nS~1 -> b\nA -> a";
+    let cfg = Cfg::try_new(script).unwrap();
+    assert!(cfg.test(split!("aab")));
+    assert!(cfg.test(split!("b")));
+    assert!(!cfg.test(split!("ab b")));
+}
+
+grammar_error!(
+    unclosed_group,
+    "S -> (a b",
+    1,
+    6,
+    GrammarErrorKind::UnclosedGroup
+);
+grammar_error!(
+    empty_group_alternative,
+    "S -> (a | ) b",
+    1,
+    9,
+    GrammarErrorKind::EmptyAlternative
+);
+
+#[test]
+fn terminal_classes_match_single_characters() {
+    let cfg = Cfg::try_new("S -> [a-z]+ [0-9]").unwrap();
+    assert!(cfg.test(split!("abc1")));
+    assert!(cfg.test(split!("z9")));
+    assert!(!cfg.test(split!("a")));
+    assert!(!cfg.test(split!("A1")));
+    assert!(!cfg.test(vec!["ab", "1"]));
+
+    let cfg = Cfg::try_with_backend("S -> [a-z]+ [0-9]", Backend::Earley).unwrap();
+    assert!(cfg.test(split!("abc1")));
+    assert!(!cfg.test(split!("A1")));
+
+    // The class as written is not one of its characters
+    for backend in [Backend::Cyk, Backend::Earley] {
+        let cfg = Cfg::try_with_backend("S -> [a-z]", backend).unwrap();
+        assert!(cfg.test(vec!["q"]));
+        assert!(!cfg.test(vec!["[a-z]"]));
+    }
+}
+
+#[test]
+fn negated_terminal_classes() {
+    let cfg = Cfg::try_new("S -> \"\\\"\" [^\"]* \"\\\"\"").unwrap();
+    assert!(cfg.test(split!("\"ab c\"")));
+    assert!(cfg.test(split!("\"\"")));
+    assert!(!cfg.test(split!("\"a\"b\"")));
+}
+
+#[test]
+fn terminal_classes_in_parse_trees() {
+    let cfg = Cfg::try_new("S -> ID = [0-9]\nID -> [a-z] [a-z]").unwrap();
+    let tree = cfg.restore_tree(&cfg.parse(split!("ab=1")).unwrap());
+    assert_eq!(shape(&tree), "S(ID(a b) = 1)");
+}
+
+#[test]
+fn lone_brackets_are_still_terminals() {
+    let cfg = Cfg::try_new("S -> [ [ab] ]").unwrap();
+    assert!(cfg.test(split!("[a]")));
+    assert!(!cfg.test(split!("[ab]")));
+}
+
+grammar_error!(
+    backwards_class,
+    "S -> a [z-a]",
+    1,
+    8,
+    GrammarErrorKind::InvalidClass("[z-a]".to_string())
+);
+
+const TOKENS: &str = "
+S -> STATEMENT+
+STATEMENT -> int ID = EXPRESSION ; | print ( EXPRESSION ) ;
+EXPRESSION -> ID | NUMBER | EXPRESSION + EXPRESSION
+ID = /[a-z][a-z0-9]*/
+NUMBER = /[0-9]+/
+";
+
+#[test]
+fn test_str_scans_declared_tokens() {
+    let cfg = Cfg::try_with_backend(TOKENS, Backend::Earley).unwrap();
+    assert!(cfg.test_str("int a = 12; print(a + 3);"));
+    assert!(cfg.test_str("int x1=0;print(x1)\n;"));
+    assert!(!cfg.test_str("int a = 12"));
+    assert!(!cfg.test_str("int a = 1 $ 2;"));
+}
+
+#[test]
+fn tokenize_prefers_the_longest_match() {
+    let cfg = Cfg::try_with_backend(TOKENS, Backend::Earley).unwrap();
+    assert_eq!(
+        cfg.tokenize("int integer = 42;").unwrap(),
+        vec!["int", "ID", "=", "NUMBER", ";"]
+    );
+    assert_eq!(cfg.tokenize("print(a) # b"), Err(9));
+}
+
+#[test]
+fn tokenize_prefers_earlier_tokens() {
+    let cfg = Cfg::try_new("S -> HEX | NUMBER\nNUMBER = /[0-9]+/\nHEX = /[0-9a-f]+/").unwrap();
+    assert_eq!(cfg.tokenize("12 1f").unwrap(), vec!["NUMBER", "HEX"]);
+
+    let cfg = Cfg::try_new("S -> HEX | NUMBER\nHEX = /[0-9a-f]+/\nNUMBER = /[0-9]+/").unwrap();
+    assert_eq!(cfg.tokenize("12 1f").unwrap(), vec!["HEX", "HEX"]);
+}
+
+#[test]
+fn tokenize_matches_classes_one_character_at_a_time() {
+    let cfg = Cfg::try_new("S -> [a-z]+ NUMBER\nNUMBER = /[0-9]+/").unwrap();
+    assert_eq!(cfg.tokenize("ab 12").unwrap(), vec!["a", "b", "NUMBER"]);
+    assert!(cfg.test_str("ab 12"));
+    assert!(!cfg.test_str("12"));
+}
+
+grammar_error!(
+    bad_token_pattern,
+    "S -> N\nN = /[0-9/",
+    2,
+    5,
+    GrammarErrorKind::InvalidToken("Unexpected end of pattern".to_string())
+);
+grammar_error!(
+    token_without_pattern,
+    "S -> N\nN = 0",
+    2,
+    3,
+    GrammarErrorKind::InvalidToken("expected /regex/ after `N =`".to_string())
+);
+
+const ATTACHMENT: &str = "
+S -> NP VP
+NP -> NP PP [0.2] | n [0.8]
+VP -> v NP [0.6] | VP PP [0.4]
+PP -> p NP
+";
+
+#[test]
+fn most_probable_parse_picks_the_likelier_tree() {
+    let input: Vec<&str> = split_space!("n v n p n");
+
+    let cfg = Cfg::try_new(ATTACHMENT).unwrap();
+    let (tree, log_prob) = cfg.most_probable_parse(input.clone()).unwrap();
+    assert_eq!(
+        shape(&cfg.restore_tree(&tree)),
+        "S(NP(n) VP(VP(v NP(n)) PP(p NP(n))))"
+    );
+    assert!((log_prob - (0.8f64 * 0.4 * 0.6 * 0.8 * 0.8).ln()).abs() < 1e-9);
+
+    // Making the other attachment likelier flips the result
+    let flipped = ATTACHMENT
+        .replace("[0.2]", "[0.9]")
+        .replace("[0.8]", "[0.1]");
+    let cfg = Cfg::try_with_backend(&flipped, Backend::Earley).unwrap();
+    let (tree, _) = cfg.most_probable_parse(input).unwrap();
+    assert_eq!(shape(&tree), "S(NP(n) VP(v NP(NP(n) PP(p NP(n)))))");
+}
+
+#[test]
+fn weights_are_carried_through_cnf() {
+    let cfg = Cfg::try_new("S -> a S [0.3] | b [0.7]").unwrap();
+    let (_, log_prob) = cfg.most_probable_parse(split!("aab")).unwrap();
+    assert!((log_prob - (0.3f64 * 0.3 * 0.7).ln()).abs() < 1e-9);
+
+    // Leaving out the nullable variable costs the probability of it being empty
+    let cfg = Cfg::try_new("S -> a A\nA -> b [0.25] | %empty").unwrap();
+    let (_, log_prob) = cfg.most_probable_parse(split!("a")).unwrap();
+    assert!((log_prob - 0.75f64.ln()).abs() < 1e-9);
+
+    assert!(cfg.most_probable_parse(split!("bb")).is_none());
+}
+
+#[test]
+fn unweighted_alternatives_share_what_is_left() {
+    let cfg = Cfg::try_new("S -> a [0.5] | b | c").unwrap();
+    let (_, log_prob) = cfg.most_probable_parse(split!("c")).unwrap();
+    assert!((log_prob - 0.25f64.ln()).abs() < 1e-9);
+
+    let cfg = Cfg::try_new("S -> a [0.2] | b [0.2]").unwrap();
+    let (_, log_prob) = cfg.most_probable_parse(split!("b")).unwrap();
+    assert!((log_prob - 0.5f64.ln()).abs() < 1e-9);
+}
+
+#[test]
+fn samples_follow_the_weights() {
+    let cfg = Cfg::try_new("S -> a [0.9] | b [0.1]").unwrap();
+    let samples = cfg.generate_sample_langauge(1000);
+    let a_count = samples.iter().filter(|s| *s == "a").count();
+    assert!((800..=980).contains(&a_count));
+}
+
+grammar_error!(
+    weights_over_one,
+    "S -> a [0.6]\n | b [0.5]",
+    2,
+    6,
+    GrammarErrorKind::WeightsOverOne("S".to_string())
+);
+grammar_error!(
+    weight_out_of_range,
+    "S -> a [1.5] | b",
+    1,
+    8,
+    GrammarErrorKind::InvalidWeight("1.5".to_string())
+);
+grammar_error!(
+    weight_ends_the_alternative,
+    "S -> a [0.5] b",
+    1,
+    14,
+    GrammarErrorKind::UnexpectedToken("b".to_string())
+);
+
+#[test]
+fn training_learns_how_often_rules_are_used() {
+    let mut cfg = Cfg::try_new("S -> a S | b").unwrap();
+    let corpus: Vec<Vec<&str>> = vec![split!("b"), split!("ab"), split!("aab"), split!("aaab")];
+    cfg.train(&corpus, 10, 1e-9);
+    assert_eq!(cfg.to_grammar_string(), "S -> a S [0.6] | b [0.4]\n");
+
+    // Leaving out a nullable variable counts as using its epsilon production
+    let mut cfg = Cfg::try_with_backend("S -> a A\nA -> b | %empty", Backend::Earley).unwrap();
+    let corpus: Vec<Vec<&str>> = vec![split!("a"), split!("ab"), split!("a"), split!("a")];
+    cfg.train(&corpus, 10, 1e-9);
+    assert_eq!(
+        cfg.to_grammar_string(),
+        "S -> a A [1.0]\nA -> b [0.25] | %empty [0.75]\n"
+    );
+}
+
+#[test]
+fn training_sums_over_every_derivation() {
+    // `a` is either A of A A, with the other one left out
+    let mut cfg = Cfg::try_new("S -> A A\nA -> a [0.5] | # [0.5]").unwrap();
+    let log_likelihoods = cfg.train(&[split!("a")], 1, 1e-9);
+    assert!((log_likelihoods[0] - 0.5f64.ln()).abs() < 1e-9);
+
+    // Through unit productions and a variable only nullable through another
+    let mut cfg = Cfg::try_new("S -> A | B c\nA -> a\nB -> C C\nC -> c [0.5] | # [0.5]").unwrap();
+    let log_likelihoods = cfg.train(&[split!("c")], 1, 1e-9);
+    assert!((log_likelihoods[0] - (0.5 * 0.25f64).ln()).abs() < 1e-9);
+}
+
+#[test]
+fn training_improves_the_likelihood() {
+    let mut cfg = Cfg::try_new("S -> S S | a | b").unwrap();
+    let corpus: Vec<Vec<&str>> = vec![
+        split!("aa"),
+        split!("aab"),
+        split!("a"),
+        split!("aaaa"),
+        split!("c"),
+    ];
+    let log_likelihoods = cfg.train(&corpus, 50, 1e-6);
+
+    assert!(log_likelihoods.len() > 1);
+    assert!(log_likelihoods.windows(2).all(|w| w[1] >= w[0] - 1e-9));
+
+    // The trained grammar reads back in with the same weights
+    let written = cfg.to_grammar_string();
+    assert_eq!(Cfg::try_new(&written).unwrap().to_grammar_string(), written);
+}
+
+#[test]
+fn written_grammars_read_back_the_same() {
+    let script = "
+        %start PROGRAM
+        STATEMENT -> print \"(\" ID \")\" [0.25] | ID \"->\" ID
+        PROGRAM -> STATEMENT+ | %empty
+        ID -> [a-z] | NAME
+        OPERATOR -> \"X*\" \"A?\"
+        NAME = /[A-Z]+/
+        PATH = /[a-z\\/]+/
+    ";
+    let written = Cfg::try_new(script).unwrap().to_grammar_string();
+    assert_eq!(
+        written,
+        "%start PROGRAM
+STATEMENT -> print \"(\" ID ) [0.25] | ID \"->\" ID [0.75]
+PROGRAM~1 -> STATEMENT PROGRAM~1 [0.5] | STATEMENT [0.5]
+PROGRAM -> PROGRAM~1 [0.5] | %empty [0.5]
+ID -> [a-z] [0.5] | NAME [0.5]
+OPERATOR -> \"X*\" \"A?\" [1.0]
+NAME = /[A-Z]+/
+PATH = /[a-z\\/]+/
+"
+    );
+
+    let cfg = Cfg::try_new(&written).unwrap();
+    assert_eq!(cfg.to_grammar_string(), written);
+    assert!(cfg.test_str("print(x) a -> BC"));
+}
+
+#[test]
+fn generation_stays_within_the_budget() {
+    // Left alone, this would almost always pick `0 S 1` and never stop
+    let cfg = Cfg::try_with_backend("S -> 0 S 1 [0.99] | %empty", Backend::Earley).unwrap();
+    let samples = cfg.generate_sample_langauge_within(50, 10);
+    assert_eq!(samples.len(), 50);
+    for sample in samples.iter() {
+        // Nine `0 S 1` and the final epsilon
+        assert!(sample.len() <= 18);
+        assert!(sample.is_empty() || cfg.test(split!(sample)));
+    }
+}
+
+#[test]
+fn generation_finishes_on_branching_grammars() {
+    let cfg = Cfg::try_new("S -> S S [0.9] | a [0.1]").unwrap();
+    let samples = cfg.generate_sample_langauge_within(20, 1000);
+    assert!(samples.iter().all(|s| !s.is_empty() && s.len() <= 1000));
+
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    for sample in cfg.generate_sample_langauge(20) {
+        assert!(cfg.test(split!(sample)), "{}", sample);
+    }
+}
+
+#[test]
+fn generation_skips_variables_that_never_finish() {
+    let cfg = Cfg::new("S -> a | B\nB -> b B");
+    assert!(cfg.generate_sample_langauge(10).iter().all(|s| s == "a"));
+
+    let cfg = Cfg::new("S -> a S");
+    assert!(cfg.generate_sample_langauge(10).is_empty());
+}
+
+#[test]
+fn generation_leaves_out_declared_tokens() {
+    // Only the pattern of a token is known, not text to write for it
+    let cfg = Cfg::try_new("S -> ID = NUM | x = 0\nID = /[a-z]+/\nNUM = /[0-9]+/").unwrap();
+    let samples = cfg.generate_sample_langauge(10);
+    assert!(samples.iter().all(|s| s == "x=0"));
+    assert!(samples.iter().all(|s| cfg.test_str(s)));
+
+    let cfg = Cfg::try_new("S -> ID = NUM\nID = /[a-z]+/\nNUM = /[0-9]+/").unwrap();
+    assert!(cfg.generate_sample_langauge(10).is_empty());
+}
+
+#[test]
+fn seeded_samples_are_reproducible() {
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    let samples = cfg.generate_seeded_samples(20, 7);
+    assert_eq!(samples.len(), 20);
+    assert_eq!(cfg.generate_seeded_samples(20, 7), samples);
+    assert_ne!(cfg.generate_seeded_samples(20, 8), samples);
+
+    for (seed, sample) in samples {
+        assert_eq!(cfg.replay_sample(seed), Some(sample));
+    }
+}
+
+#[test]
+fn samples_can_use_any_rng() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let cfg = Cfg::try_new("S -> a S | b").unwrap();
+    let first = cfg.generate_sample_langauge_with_rng(10, 20, &mut ChaCha8Rng::seed_from_u64(1));
+    let second = cfg.generate_sample_langauge_with_rng(10, 20, &mut ChaCha8Rng::seed_from_u64(1));
+    assert_eq!(first, second);
+}
+
+#[test]
+fn strings_of_a_length_are_equally_likely() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::HashMap;
+
+    let cfg = Cfg::try_new("S -> a S | b S | a | b").unwrap();
+    let mut rng = ChaCha8Rng::seed_from_u64(3);
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for _ in 0..800 {
+        let sample = cfg.generate_of_length(3, &mut rng).unwrap();
+        *seen.entry(sample).or_default() += 1;
+    }
+
+    assert_eq!(seen.len(), 8);
+    assert!(seen.values().all(|count| (50..150).contains(count)));
+}
+
+#[test]
+fn generated_strings_have_the_length_asked_for() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(5);
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    for len in 1..8 {
+        let sample = cfg.generate_of_length(len, &mut rng).unwrap();
+        assert_eq!(sample.len(), len);
+        assert!(cfg.test(split!(sample)), "{}", sample);
+    }
+
+    let cfg = Cfg::try_new("S -> 0 S 1 | 0 1").unwrap();
+    assert_eq!(cfg.generate_of_length(6, &mut rng).unwrap(), "000111");
+    assert!(cfg.generate_of_length(5, &mut rng).is_none());
+    assert!(cfg.generate_of_length(0, &mut rng).is_none());
+}
+
+#[test]
+fn count_matches_known_sequences() {
+    use num_bigint::BigUint;
+
+    // Every string over {a, b}
+    let cfg = Cfg::try_new("S -> a S | b S | a | b").unwrap();
+    assert_eq!(cfg.count(3), BigUint::from(8u32));
+    assert_eq!(cfg.count(100), BigUint::from(2u32).pow(100));
+
+    // Binary trees with n leaves, counted by the Catalan numbers
+    let cfg = Cfg::try_with_backend("S -> S S | a", Backend::Earley).unwrap();
+    let catalan: Vec<u32> = (1..=8).map(|n| cfg.count(n).try_into().unwrap()).collect();
+    assert_eq!(catalan, vec![1, 1, 2, 5, 14, 42, 132, 429]);
+    assert_eq!(cfg.count(20), BigUint::from(1_767_263_190u64));
+
+    assert_eq!(cfg.count(0), BigUint::ZERO);
+}
+
+#[test]
+fn count_follows_the_grammar_as_written() {
+    use num_bigint::BigUint;
+
+    // Either A can be the one left out
+    let cfg = Cfg::try_new("S -> A A\nA -> a | #").unwrap();
+    assert_eq!(cfg.count(1), BigUint::from(2u32));
+    assert_eq!(cfg.count(2), BigUint::from(1u32));
+
+    let cfg = Cfg::try_new("S -> A | B\nA -> a\nB -> a").unwrap();
+    assert_eq!(cfg.count(1), BigUint::from(2u32));
+}
+
+#[test]
+fn count_includes_every_class_member() {
+    use num_bigint::BigUint;
+
+    let cfg = Cfg::try_new("S -> [a-c] [0-9]").unwrap();
+    assert_eq!(cfg.count(2), BigUint::from(30u32));
+    assert_eq!(cfg.count(3), BigUint::ZERO);
+}
+
+fn first_strings(cfg: &Cfg, n: usize) -> Vec<String> {
+    cfg.enumerate().take(n).map(|s| s.join(" ")).collect()
+}
+
+#[test]
+fn enumerate_in_shortlex_order() {
+    let cfg = Cfg::try_new("S -> b S | a S | a | b").unwrap();
+    assert_eq!(
+        first_strings(&cfg, 8),
+        vec!["a", "b", "a a", "a b", "b a", "b b", "a a a", "a a b"]
+    );
+
+    let cfg = Cfg::try_with_backend("S -> 0 S 1 | 0 1", Backend::Earley).unwrap();
+    assert_eq!(
+        first_strings(&cfg, 3),
+        vec!["0 1", "0 0 1 1", "0 0 0 1 1 1"]
+    );
+}
+
+#[test]
+fn enumerate_skips_repeats_of_ambiguous_strings() {
+    let cfg = Cfg::try_new("S -> S S | a").unwrap();
+    assert_eq!(first_strings(&cfg, 4), vec!["a", "a a", "a a a", "a a a a"]);
+
+    let cfg = Cfg::try_new("S -> X | Y\nX -> a b | a\nY -> a b | b").unwrap();
+    assert_eq!(first_strings(&cfg, 10), vec!["a", "b", "a b"]);
+}
+
+#[test]
+fn enumerate_works_out_only_the_strings_asked_for() {
+    // 26^8 strings of length 8, and far more below it
+    let cfg = Cfg::try_new("S -> [a-z] S | [a-z]").unwrap();
+    let strings: Vec<String> = cfg
+        .enumerate()
+        .starting_at(8)
+        .take(3)
+        .map(|s| s.concat())
+        .collect();
+    assert_eq!(strings, vec!["aaaaaaaa", "aaaaaaab", "aaaaaaac"]);
+
+    let cfg = Cfg::try_new("S -> S S | [a-z] [0-9]").unwrap();
+    let strings: Vec<String> = cfg
+        .enumerate()
+        .starting_at(6)
+        .take(2)
+        .map(|s| s.concat())
+        .collect();
+    assert_eq!(strings, vec!["a0a0a0", "a0a0a1"]);
+}
+
+#[test]
+fn enumerate_ends_for_finite_languages() {
+    let cfg = Cfg::try_new("S -> [ab] C\nC -> c | c c").unwrap();
+    assert_eq!(
+        first_strings(&cfg, 10),
+        vec!["a c", "b c", "a c c", "b c c"]
+    );
+
+    // B never finishes, so it cannot make the language infinite
+    let cfg = Cfg::new("S -> a | a B\nB -> b B");
+    assert_eq!(first_strings(&cfg, 10), vec!["a"]);
+}
+
+#[test]
+fn rewriting_ambiguous_rules_keeps_the_language() {
+    use super::equivalence::{bounded_equivalence, Equivalence};
+
+    let ambiguous = Cfg::try_new("E -> E + E | a | b").unwrap();
+    let layered = Cfg::try_new("E -> a | b | T + E\nT -> a | b").unwrap();
+    assert_eq!(
+        bounded_equivalence(&ambiguous, &layered, 7),
+        Equivalence::EquivalentUpTo(7)
+    );
+
+    // The example grammar with its expressions written without ambiguity
+    let original = include_str!("../../cfg1.txt");
+    let rewritten = original.replace(
+        "EXPRESSION -> ID | EXPRESSION OPERATOR EXPRESSION | LITERAL",
+        "EXPRESSION -> ID | LITERAL | TERM OPERATOR EXPRESSION\nTERM -> ID | LITERAL",
+    );
+    let original = Cfg::try_new(original).unwrap();
+    let rewritten = Cfg::try_new(&rewritten).unwrap();
+    assert_eq!(
+        bounded_equivalence(&original, &rewritten, 9),
+        Equivalence::EquivalentUpTo(9)
+    );
+}
+
+#[test]
+fn finds_the_shortest_counterexample() {
+    use super::equivalence::{bounded_equivalence, Equivalence};
+
+    let first = Cfg::try_new("E -> a | b | T + E\nT -> a | b").unwrap();
+    let second = Cfg::try_new("E -> a | b | T + E | T T\nT -> a | b").unwrap();
+    assert_eq!(
+        bounded_equivalence(&first, &second, 5),
+        Equivalence::Counterexample {
+            string: vec!["a".to_string(), "a".to_string()],
+            accepted_by_first: false,
+        }
+    );
+
+    // The empty string comes before everything else
+    let nullable = Cfg::try_new("S -> a | #").unwrap();
+    let not_nullable = Cfg::try_new("S -> a").unwrap();
+    assert_eq!(
+        bounded_equivalence(&nullable, &not_nullable, 5),
+        Equivalence::Counterexample {
+            string: vec![],
+            accepted_by_first: true,
+        }
+    );
+
+    let third = Cfg::try_new("E -> a | T + E\nT -> a").unwrap();
+    assert_eq!(
+        bounded_equivalence(&first, &third, 5),
+        Equivalence::Counterexample {
+            string: vec!["b".to_string()],
+            accepted_by_first: true,
+        }
+    );
+}
+
+#[test]
+fn analysis_finds_productive_and_reachable_variables() {
+    let cfg = Cfg::try_new("S -> a | A B\nA -> a A\nB -> b\nC -> c").unwrap();
+    let analysis = cfg.analyze();
+
+    let set = |names: &[&str]| names.iter().map(|s| s.to_string()).collect();
+    assert_eq!(analysis.productive, set(&["B", "C", "S"]));
+    assert_eq!(analysis.reachable, set(&["A", "B", "S"]));
+    assert!(!analysis.is_empty);
+    assert_eq!(analysis.finite_language, Some(vec![vec!["a".to_string()]]));
+}
+
+#[test]
+fn analysis_decides_emptiness_and_finiteness() {
+    let empty = Cfg::try_new("S -> a S").unwrap().analyze();
+    assert!(empty.is_empty);
+    assert_eq!(empty.finite_language, Some(vec![]));
+
+    let infinite = Cfg::try_new("S -> a S | b").unwrap().analyze();
+    assert!(!infinite.is_empty);
+    assert!(!infinite.is_finite());
+
+    let with_epsilon = Cfg::try_new("S -> a | #").unwrap().analyze();
+    assert_eq!(
+        with_epsilon.finite_language,
+        Some(vec![vec![], vec!["a".to_string()]])
+    );
+}
+
+#[test]
+fn variables_that_only_derive_epsilon_are_not_terminals() {
+    let strings = |strings: &[&[&str]]| -> Vec<Vec<String>> {
+        strings
+            .iter()
+            .map(|s| s.iter().map(|t| t.to_string()).collect())
+            .collect()
+    };
+
+    let cfg = Cfg::try_new("S -> a | B\nB -> #").unwrap();
+    assert!(!cfg.test(vec!["B"]));
+    assert!(cfg.test(vec!["a"]));
+    assert_eq!(cfg.enumerate().collect::<Vec<_>>(), strings(&[&["a"]]));
+    assert_eq!(cfg.analyze().finite_language, Some(strings(&[&[], &["a"]])));
+
+    let cfg = Cfg::try_new("S -> #").unwrap();
+    assert!(!cfg.test(vec!["S"]));
+    assert_eq!(cfg.enumerate().count(), 0);
+    let analysis = cfg.analyze();
+    assert!(!analysis.is_empty);
+    assert_eq!(analysis.finite_language, Some(strings(&[&[]])));
+}
+
+#[test]
+fn lint_reports_each_problem_on_its_line() {
+    use super::lint::{Warning, WarningKind};
+
+    let warnings = Cfg::lint_str(
+        "S -> EXPR | UNUSED\n\
+         EXPR -> EXPR + TERM | TERM | TERM\n\
+         TERM -> a | EXPRESION\n\
+         UNUSED -> b\n\
+         LOOP -> x LOOP",
+    )
+    .unwrap();
+
+    let names = |names: &[&str]| names.iter().map(|s| s.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec![
+            Warning {
+                line: 2,
+                kind: WarningKind::DuplicateAlternative("EXPR -> TERM".to_string()),
+            },
+            Warning {
+                line: 2,
+                kind: WarningKind::LeftRecursion(names(&["EXPR", "EXPR"])),
+            },
+            Warning {
+                line: 3,
+                kind: WarningKind::UndefinedSymbol("EXPRESION".to_string()),
+            },
+            Warning {
+                line: 5,
+                kind: WarningKind::Unreachable("LOOP".to_string()),
+            },
+            Warning {
+                line: 5,
+                kind: WarningKind::Unproductive("LOOP".to_string()),
+            },
+        ]
+    );
+    assert_eq!(
+        warnings[2].to_string(),
+        "line 3: `EXPRESION` has no rules and is read as a terminal"
+    );
+}
+
+#[test]
+fn lint_warns_about_operators_read_as_terminals() {
+    use super::lint::WarningKind;
+
+    // Written with spaces, the parentheses are terminals and `|` splits the rule
+    let warnings = Cfg::lint_str("S -> ( A | B )\nA -> a\nB -> b").unwrap();
+    assert_eq!(
+        warnings.iter().map(|w| &w.kind).collect::<Vec<_>>(),
+        vec![&WarningKind::UnbalancedParenthesis("S -> ( A".to_string())]
+    );
+    assert!(Cfg::lint_str("S -> (A | B)\nA -> a\nB -> b")
+        .unwrap()
+        .is_empty());
+    assert!(Cfg::lint_str("S -> ( S ) | a").unwrap().is_empty());
+
+    let warnings = Cfg::lint_str("S -> b* c \"d+\" \"e\"+ *").unwrap();
+    assert_eq!(
+        warnings.iter().map(|w| &w.kind).collect::<Vec<_>>(),
+        vec![&WarningKind::OperatorOnTerminal("b*".to_string())]
+    );
+    assert_eq!(
+        warnings[0].to_string(),
+        "line 1: `b*` is read as a terminal, `?`, `*` and `+` only apply to variables, \
+         quoted terminals, classes and groups"
+    );
+}
+
+#[test]
+fn lint_finds_unit_cycles_and_indirect_left_recursion() {
+    use super::lint::WarningKind;
+
+    let names = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    let warnings = Cfg::lint_str("A -> B | a\nB -> C | b\nC -> A").unwrap();
+    let kinds: Vec<WarningKind> = warnings.into_iter().map(|w| w.kind).collect();
+    assert!(kinds.contains(&WarningKind::UnitCycle(names(&["A", "B", "C", "A"]))));
+
+    // The nullable `N` in front of `B` does not hide the recursion
+    let warnings = Cfg::lint_str("A -> N B c | a\nB -> A b\nN -> n | %empty").unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].line, 1);
+    assert_eq!(
+        warnings[0].kind,
+        WarningKind::LeftRecursion(names(&["A", "B", "A"]))
+    );
+
+    // Quoted terminals and tokens are not undefined variables
+    let warnings = Cfg::lint_str("S -> \"ID\" NUM\nNUM = /[0-9]+/").unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn unit_cycles_are_removed() {
+    let cfg = Cfg::try_new("A -> B | a\nB -> A | b").unwrap();
+    assert!(cfg.test(vec!["a"]));
+    assert!(cfg.test(vec!["b"]));
+    assert!(!cfg.test(vec!["a", "b"]));
+
+    // A cycle through the starting variable, with a way out at each step
+    let cfg = Cfg::try_new("S -> A | S s\nA -> B | a\nB -> S | b").unwrap();
+    assert!(cfg.test(split!("b")));
+    assert!(cfg.test(split!("ass")));
+    assert!(!cfg.test(split!("sa")));
+
+    // A variable that only leads back to itself derives nothing
+    let cfg = Cfg::new("S -> A | c\nA -> A");
+    assert!(cfg.test(vec!["c"]));
+    assert_eq!(cfg.count(1), 1u32.into());
+}
+
+#[test]
+fn nested_unit_chains_keep_every_production() {
+    let cfg = Cfg::try_new("S -> E\nE -> T | T + E\nT -> F | F * T\nF -> a | b | ( E )").unwrap();
+    assert!(cfg.test(split!("a")));
+    assert!(cfg.test(split!("a+b*a")));
+    assert!(cfg.test(split!("(a+b)*a")));
+    assert!(!cfg.test(split!("a+")));
+
+    // The chain is put back in the tree, and `b` is derived only once
+    let tree = cfg.restore_tree(&cfg.parse(split!("b")).unwrap());
+    assert_eq!(shape(&tree), "S(E(T(F(b))))");
+    assert_eq!(cfg.count(1), 2u32.into());
+}
+
+#[test]
+fn layered_and_ambiguous_expressions_agree() {
+    use super::equivalence::{bounded_equivalence, Equivalence};
+
+    let ambiguous = Cfg::try_new("E -> E + E | E * E | a | b").unwrap();
+    let layered = Cfg::try_new("E -> T | T + E\nT -> F | F * T\nF -> a | b").unwrap();
+    assert_eq!(
+        bounded_equivalence(&ambiguous, &layered, 7),
+        Equivalence::EquivalentUpTo(7)
+    );
+}
+
+#[test]
+fn made_up_symbols_do_not_collide_with_written_ones() {
+    // Splitting `x A B` would make a helper for `A B`, which must not be the written `AB`
+    let cfg = Cfg::try_new("S -> x A B | AB\nA -> a\nB -> b\nAB -> c").unwrap();
+    assert!(cfg.test(split!("xab")));
+    assert!(cfg.test(split!("c")));
+    assert!(!cfg.test(split!("xc")));
+    assert!(!cfg.test(split!("ab")));
+
+    // Nor must the new starting variable or the variable standing for a terminal
+    let cfg = Cfg::try_new("S -> S` S | a`\nS` -> a\na` -> b").unwrap();
+    assert!(cfg.test(split!("b")));
+    assert!(cfg.test(split!("ab")));
+    assert!(!cfg.test(split!("a")));
+}
+
+#[test]
+fn shared_endings_are_split_off_once() {
+    // Every string of the layered grammar has one derivation
+    let cfg = Cfg::try_new("E -> T | T + E\nT -> F | F * T\nF -> a | b | ( E )").unwrap();
+    assert_eq!(cfg.count(3), 10u32.into());
+}
+
+// The first strings of each grammar the recognizers are checked on, then `count` random
+// strings of each length made of the terminals in them, mostly not in the language
+fn recognizer_inputs(
+    rng: &mut rand_chacha::ChaCha8Rng,
+    lengths: &[usize],
+    count: usize,
+) -> Vec<(Cfg, Vec<Vec<String>>)> {
+    use rand::seq::SliceRandom;
+
+    [
+        include_str!("../../cfg.txt"),
+        include_str!("../../cfg1.txt"),
+        "S -> S S | ( S ) | [ S ] | a",
+        "S -> [a-c]+ T\nT -> 0 T 1 | %empty",
+    ]
+    .iter()
+    .map(|script| {
+        let cfg = Cfg::try_new(script).unwrap();
+        let mut inputs: Vec<Vec<String>> = cfg.enumerate().take(200).collect();
+        let terminals: Vec<String> = inputs.iter().flatten().cloned().collect();
+
+        for len in lengths.iter() {
+            for _ in 0..count {
+                let input = (0..*len)
+                    .map(|_| terminals.choose(rng).unwrap().clone())
+                    .collect();
+                inputs.push(input);
+            }
+        }
+
+        (cfg, inputs)
+    })
+    .collect()
+}
+
+#[test]
+fn recognizer_agrees_with_the_naive_table() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    // Mostly strings that are not in the language
+    let mut rng = ChaCha8Rng::seed_from_u64(11);
+    let lengths: Vec<usize> = (1..12).collect();
+    for (cfg, inputs) in recognizer_inputs(&mut rng, &lengths, 20) {
+        for input in inputs.iter() {
+            let input: Vec<&str> = input.iter().map(|s| s.as_str()).collect();
+            assert_eq!(
+                cfg.test(input.clone()),
+                cfg.test_naive(input.clone()),
+                "{:?}",
+                input
+            );
+        }
+    }
+}
+
+// Run with `cargo test --release -- --ignored --nocapture` to see the timings
+#[test]
+#[ignore]
+fn benchmark_recognizer_against_the_naive_table() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::time::Instant;
+
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    for len in [50, 100, 200] {
+        let sample = cfg.generate_of_length(len, &mut rng).unwrap();
+        let input: Vec<&str> = split!(sample);
+
+        let start = Instant::now();
+        assert!(cfg.test(input.clone()));
+        let fast = start.elapsed();
+
+        let start = Instant::now();
+        assert!(cfg.test_naive(input.clone()));
+        let naive = start.elapsed();
+
+        println!(
+            "{} tokens: {:?} against {:?}, {:.1}x faster",
+            len,
+            fast,
+            naive,
+            naive.as_secs_f64() / fast.as_secs_f64()
+        );
+    }
+
+    // Too long for the naive table to be worth waiting for.
+    // Patterns one after another are a pattern too
+    let sample: String = (0..20)
+        .map(|_| cfg.generate_of_length(50, &mut rng).unwrap())
+        .collect();
+    let start = Instant::now();
+    assert!(cfg.test(split!(sample)));
+    println!("1000 tokens: {:?}", start.elapsed());
+}
+
+#[test]
+fn parallel_testing_gives_the_sequential_answers() {
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(13);
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    let terminals: Vec<&str> = split!("ab(){},[]*0123");
+
+    let mut inputs: Vec<Vec<&str>> = Vec::new();
+    let mut samples = Vec::new();
+    for len in [1, 5, 40, 80] {
+        samples.push(cfg.generate_of_length(len, &mut rng).unwrap());
+    }
+    for sample in samples.iter() {
+        inputs.push(split!(sample));
+    }
+    for len in [3, 60, 100] {
+        inputs.push(
+            (0..len)
+                .map(|_| *terminals.choose(&mut rng).unwrap())
+                .collect(),
+        );
+    }
+    // Changing one token of a long sample mostly breaks it
+    let mut broken = inputs[3].clone();
+    broken[40] = "}";
+    inputs.push(broken);
+
+    let sequential: Vec<bool> = inputs.iter().map(|i| cfg.test(i.clone())).collect();
+    assert!(sequential[..4].iter().all(|accepted| *accepted));
+    assert_eq!(cfg.test_many(&inputs), sequential);
+    for (input, expected) in inputs.iter().zip(sequential) {
+        assert_eq!(cfg.test_parallel(input.clone(), 4), expected);
+    }
+
+    let earley = Cfg::try_with_backend("S -> a S b | %empty", Backend::Earley).unwrap();
+    let inputs = vec![split!("ab"), split!("aabb"), split!("aab")];
+    assert_eq!(earley.test_many(&inputs), vec![true, true, false]);
+}
+
+// Run with `cargo test --release -- --ignored --nocapture` to see the timings
+#[test]
+#[ignore]
+fn benchmark_parallel_diagonals() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::time::Instant;
+
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    let sample: String = (0..20)
+        .map(|_| cfg.generate_of_length(50, &mut rng).unwrap())
+        .collect();
+    let input: Vec<&str> = split!(sample);
+
+    let start = Instant::now();
+    assert!(cfg.test(input.clone()));
+    let sequential = start.elapsed();
+    for threads in [2, 4, 8] {
+        let start = Instant::now();
+        assert!(cfg.test_parallel(input.clone(), threads));
+        let parallel = start.elapsed();
+        println!(
+            "1000 tokens on {} threads: {:?} against {:?}, {:.1}x faster",
+            threads,
+            parallel,
+            sequential,
+            sequential.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
+}
+
+#[test]
+fn valiant_agrees_with_cyk() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    // Lengths around the powers of two the chart is padded to
+    let mut rng = ChaCha8Rng::seed_from_u64(17);
+    for (cfg, inputs) in recognizer_inputs(&mut rng, &[1, 2, 3, 7, 8, 15, 16, 31, 63, 64], 5) {
+        for input in inputs.iter() {
+            let input: Vec<&str> = input.iter().map(|s| s.as_str()).collect();
+            assert_eq!(
+                cfg.test_valiant(input.clone()),
+                cfg.test(input.clone()),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    // Long accepted inputs, so every block of the chart is used
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    for (count, len) in [(9, 7), (8, 8), (10, 10)] {
+        // Patterns one after another are a pattern too
+        let sample: String = (0..count)
+            .map(|_| cfg.generate_of_length(len, &mut rng).unwrap())
+            .collect();
+        assert!(cfg.test_valiant(split!(sample)), "{}", sample);
+    }
+
+    let cfg = Cfg::try_with_backend("S -> a S b | %empty", Backend::Earley).unwrap();
+    assert!(cfg.test_valiant(split!("aabb")));
+    assert!(!cfg.test_valiant(split!("aab")));
+}
+
+// Run with `cargo test --release -- --ignored --nocapture` to see the timings
+#[test]
+#[ignore]
+fn benchmark_valiant_against_cyk() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::time::Instant;
+
+    let cfg = Cfg::try_new(include_str!("../../cfg.txt")).unwrap();
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    for len in [10, 25, 50, 100, 200, 500, 1000, 2000] {
+        // Patterns one after another are a pattern too
+        let sample: String = (0..len / 10)
+            .map(|_| cfg.generate_of_length(10, &mut rng).unwrap())
+            .collect();
+        let input: Vec<&str> = split!(sample);
+
+        let start = Instant::now();
+        assert!(cfg.test_valiant(input.clone()));
+        let valiant = start.elapsed();
+
+        let start = Instant::now();
+        assert!(cfg.test(input.clone()));
+        let cyk = start.elapsed();
+
+        println!(
+            "{} tokens: {:?} against {:?}, {:.2}x as fast",
+            len,
+            valiant,
+            cyk,
+            cyk.as_secs_f64() / valiant.as_secs_f64()
+        );
+    }
+}
+
+#[test]
+fn incremental_parser_follows_the_tokens() {
+    let cfg = Cfg::try_new(include_str!("../../cfg1.txt")).unwrap();
+    let mut parser = cfg.incremental_parser();
+    assert!(parser.is_viable_prefix());
+    assert!(!parser.is_accepted());
+
+    let tokens: Vec<&str> = split_space!("{ int a = 1 ; }");
+    for (i, token) in tokens.iter().enumerate() {
+        assert!(parser.push(token), "{}", token);
+        assert!(parser.is_viable_prefix());
+        assert_eq!(parser.is_accepted(), i == tokens.len() - 1);
+        assert_eq!(
+            parser.is_accepted(),
+            cfg.test(tokens[..=i].to_vec()),
+            "{:?}",
+            &tokens[..=i]
+        );
+    }
+
+    // Nothing can follow a whole program, and nothing can fix it afterwards
+    assert!(!parser.push("}"));
+    assert!(!parser.is_viable_prefix());
+    assert!(!parser.push(";"));
+    assert!(!parser.is_accepted());
+}
+
+#[test]
+fn viable_prefixes_must_be_finishable() {
+    // `a b` could start `a B`, but B never finishes
+    let cfg = Cfg::new("S -> a c | a B\nB -> b B");
+    let mut parser = cfg.incremental_parser();
+    assert!(parser.push("a"));
+    assert!(!parser.is_accepted());
+    assert!(!parser.push("b"));
+
+    let mut parser = cfg.incremental_parser();
+    assert!(parser.push("a"));
+    assert!(parser.push("c"));
+    assert!(parser.is_accepted());
+
+    // A language with no strings has no viable prefixes
+    let cfg = Cfg::new("S -> a S");
+    assert!(!cfg.incremental_parser().is_viable_prefix());
+}
+
+#[test]
+fn expected_next_suggests_what_can_follow() {
+    let cfg = Cfg::try_new(include_str!("../../cfg1.txt")).unwrap();
+    let set = |names: &str| names.split(' ').map(|s| s.to_string()).collect();
+
+    assert_eq!(cfg.expected_next(vec![]), set("{"));
+    assert_eq!(cfg.expected_next(split_space!("{")), set("float int print"));
+    assert_eq!(
+        cfg.expected_next(split_space!("{ int a =")),
+        set("0 1 2 3 4 5 6 7 8 9 a b c")
+    );
+    assert_eq!(
+        cfg.expected_next(split_space!("{ int a = b")),
+        set("* + - / ;")
+    );
+    assert!(cfg.expected_next(split_space!("{ int =")).is_empty());
+
+    // Classes are suggested as written
+    let cfg = Cfg::try_new("S -> [a-z]+ ;").unwrap();
+    assert_eq!(cfg.expected_next(split_space!("x")), set("; [a-z]"));
+}
+
+#[test]
+fn minimal_completions_finish_the_prefix() {
+    let cfg = Cfg::try_new(include_str!("../../cfg1.txt")).unwrap();
+    let completions = cfg.minimal_completions(split_space!("{ int a ="), 3);
+    assert_eq!(completions.len(), 13);
+    assert_eq!(completions[0], vec!["0", ";", "}"]);
+    let prefix: Vec<&str> = split_space!("{ int a =");
+    for completion in completions.iter() {
+        let mut input = prefix.clone();
+        input.extend(completion.iter().map(|s| s.as_str()));
+        assert!(cfg.test(input));
+    }
+
+    // Too long to finish within the bound
+    assert!(cfg
+        .minimal_completions(split_space!("{ int a ="), 2)
+        .is_empty());
+    // Already finished
+    assert_eq!(
+        cfg.minimal_completions(split_space!("{ print ( a ) ; }"), 3),
+        vec![Vec::<String>::new()]
+    );
+    assert!(cfg.minimal_completions(split_space!("{ }"), 3).is_empty());
+
+    // A class is finished with a character it matches, never with its written text
+    let cfg = Cfg::try_new("S -> [a-z] [0-9]").unwrap();
+    assert_eq!(
+        cfg.minimal_completions(vec!["a"], 2),
+        vec![vec!["0".to_string()]]
+    );
+    let completions = cfg.minimal_completions(vec![], 2);
+    assert_eq!(completions, vec![vec!["a".to_string(), "0".to_string()]]);
+    assert!(cfg.test(completions[0].iter().map(|s| s.as_str()).collect()));
+}
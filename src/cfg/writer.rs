@@ -0,0 +1,98 @@
+// Writes grammars back out in the format read by `reader`, weights included
+use std::collections::{HashMap, HashSet};
+
+use super::production::Production;
+use super::reader::looks_like_variable;
+use super::terminal_class::TerminalClass;
+
+// One line per variable in the order they were first written, then the token declarations
+pub fn write_grammar(
+    starting_variable: &str,
+    productions: &[Production],
+    classes: &HashMap<String, TerminalClass>,
+    tokens: &[(String, String)],
+) -> String {
+    let mut variables: Vec<&str> = Vec::new();
+    for prod in productions.iter() {
+        if !variables.contains(&prod.symbol.as_str()) {
+            variables.push(&prod.symbol);
+        }
+    }
+    // Token names are written bare like variables
+    let defined: HashSet<&str> = variables
+        .iter()
+        .copied()
+        .chain(tokens.iter().map(|(name, _)| name.as_str()))
+        .collect();
+
+    let mut lines = Vec::new();
+    if variables.first() != Some(&starting_variable) {
+        lines.push(format!("%start {}", starting_variable));
+    }
+
+    for variable in variables {
+        let alternatives: Vec<String> = productions
+            .iter()
+            .filter(|p| p.symbol == variable)
+            .map(|p| {
+                let symbols: Vec<String> = if p.is_null() {
+                    vec!["%empty".to_string()]
+                } else {
+                    p.value
+                        .iter()
+                        .map(|s| write_symbol(s, &defined, classes))
+                        .collect()
+                };
+                format!("{} [{}]", symbols.join(" "), write_weight(p.weight))
+            })
+            .collect();
+
+        lines.push(format!("{} -> {}", variable, alternatives.join(" | ")));
+    }
+
+    for (name, pattern) in tokens.iter() {
+        lines.push(format!("{} = /{}/", name, write_pattern(pattern)));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+// Terminals are quoted if they would be read back as something else
+fn write_symbol(
+    symbol: &str,
+    defined: &HashSet<&str>,
+    classes: &HashMap<String, TerminalClass>,
+) -> String {
+    if defined.contains(symbol) || classes.contains_key(symbol) {
+        return symbol.to_string();
+    }
+
+    // A variable-like name followed by `?`, `*` or `+` would be read as a repetition of it
+    let stem = symbol.trim_end_matches(['?', '*', '+']);
+    let special = matches!(symbol, "|" | "->")
+        || symbol.starts_with(['%', '(', '[', '/'])
+        || symbol.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\')
+        || looks_like_variable(symbol)
+        || stem != symbol && looks_like_variable(stem);
+    if !special {
+        return symbol.to_string();
+    }
+
+    let escaped = symbol.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+// Patterns are read like quoted terminals, with `/` closing them instead
+fn write_pattern(pattern: &str) -> String {
+    pattern.replace('\\', "\\\\").replace('/', "\\/")
+}
+
+// The shortest form that reads back as the same number, always with a decimal point
+fn write_weight(weight: f64) -> String {
+    let written = weight.to_string();
+    if written.contains('.') {
+        written
+    } else {
+        written + ".0"
+    }
+}
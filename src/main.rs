@@ -1,10 +1,45 @@
 mod cfg;
 mod regex;
+use cfg::cfg::Cfg;
 use regex::regex::Regex;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // `lint FILE` checks a grammar file and prints what it finds
+    if let [_, mode, path] = args.as_slice() {
+        if mode == "lint" {
+            std::process::exit(lint(path));
+        }
+    }
+
     let regex = Regex::new("a{1,2}");
     let input = "aaa";
 
     println!("{:?}", regex.test(input));
 }
+
+// Prints the warnings for the grammar file, returning the exit code
+fn lint(path: &str) -> i32 {
+    let input = match std::fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+    };
+
+    match Cfg::lint_str(&input) {
+        Ok(warnings) if warnings.is_empty() => 0,
+        Ok(warnings) => {
+            for warning in warnings.iter() {
+                println!("{}: {}", path, warning);
+            }
+            1
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            2
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod parser;
 pub mod patterns;
+#[allow(clippy::module_inception)]
 pub mod regex;
@@ -2,66 +2,75 @@ use super::patterns::*;
 use super::regex::*;
 
 pub fn parse_regex(pattern: &str) -> Regex {
+    try_parse_regex(pattern).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_parse_regex(pattern: &str) -> Result<Regex, String> {
     let mut patterns: Vec<Box<dyn TestablePattern>> = Vec::new();
     let chars: Vec<_> = pattern.chars().collect();
 
     let mut i = 0;
     while i < chars.len() {
-        let token_selector = parse_token_selector(&chars, &mut i);
-        patterns.push(parse_amount_specifier(&chars, &mut i, token_selector));
+        let token_selector = parse_token_selector(&chars, &mut i)?;
+        patterns.push(parse_amount_specifier(&chars, &mut i, token_selector)?);
     }
 
-    Regex { patterns }
+    Ok(Regex { patterns })
 }
 
-fn parse_token_selector(chars: &[char], i: &mut usize) -> Box<dyn TokenSelector> {
+fn parse_token_selector(chars: &[char], i: &mut usize) -> Result<Box<dyn TokenSelector>, String> {
     if chars[*i] == '[' {
-        consume_char(chars[*i], '[', i);
+        consume_char(chars, '[', i)?;
         let mut tokens = Vec::new();
-        while chars[*i] != ']' {
-            tokens.push(chars[*i]);
-            *i += 1;
+        while peek(chars, *i)? != ']' {
+            // a-z is every char from a to z
+            if chars.get(*i + 1) == Some(&'-') && chars.get(*i + 2).is_some_and(|c| *c != ']') {
+                tokens.extend(chars[*i]..=chars[*i + 2]);
+                *i += 3;
+            } else {
+                tokens.push(chars[*i]);
+                *i += 1;
+            }
         }
 
-        consume_char(chars[*i], ']', i);
-        Box::new(MultiCharSelector { tokens })
+        consume_char(chars, ']', i)?;
+        Ok(Box::new(MultiCharSelector { tokens }))
     } else {
         let ret = Box::new(SingleCharSelector { token: chars[*i] });
         *i += 1;
-        ret
+        Ok(ret)
     }
 }
 
-fn parse_number_specifier(chars: &[char], i: &mut usize) -> (usize, usize) {
-    consume_char(chars[*i], '{', i);
+fn parse_number_specifier(chars: &[char], i: &mut usize) -> Result<(usize, usize), String> {
+    consume_char(chars, '{', i)?;
     let mut min_str = String::new();
-    while chars[*i].is_ascii_digit() {
+    while peek(chars, *i)?.is_ascii_digit() {
         min_str.push(chars[*i]);
         *i += 1;
     }
 
-    consume_char(chars[*i], ',', i);
+    consume_char(chars, ',', i)?;
     let mut max_str = String::new();
-    while chars[*i].is_ascii_digit() {
+    while peek(chars, *i)?.is_ascii_digit() {
         max_str.push(chars[*i]);
         *i += 1;
     }
 
-    consume_char(chars[*i], '}', i);
-    (
-        min_str.parse::<usize>().unwrap(),
-        max_str.parse::<usize>().unwrap(),
-    )
+    consume_char(chars, '}', i)?;
+    let min = min_str.parse::<usize>().map_err(|e| e.to_string())?;
+    let max = max_str.parse::<usize>().map_err(|e| e.to_string())?;
+    Ok((min, max))
 }
 
 fn parse_amount_specifier(
     chars: &[char],
     i: &mut usize,
     token_selector: Box<dyn TokenSelector>,
-) -> Box<dyn TestablePattern> {
+) -> Result<Box<dyn TestablePattern>, String> {
     let next_char = chars.get(*i).unwrap_or(&'\0');
 
-    match next_char {
+    let pattern: Box<dyn TestablePattern> = match next_char {
         '*' => {
             *i += 1;
             Box::new(AnyAmountPattern { token_selector })
@@ -75,7 +84,7 @@ fn parse_amount_specifier(
             Box::new(OptionalPattern { token_selector })
         }
         '{' => {
-            let (min, max) = parse_number_specifier(chars, i);
+            let (min, max) = parse_number_specifier(chars, i)?;
             Box::new(BoundedAmountPattern {
                 token_selector,
                 min_amount: min,
@@ -87,13 +96,24 @@ fn parse_amount_specifier(
             token_selector,
             amount: 1,
         }),
-    }
+    };
+
+    Ok(pattern)
+}
+
+fn peek(chars: &[char], index: usize) -> Result<char, String> {
+    chars
+        .get(index)
+        .copied()
+        .ok_or_else(|| "Unexpected end of pattern".to_string())
 }
 
-fn consume_char(input: char, expected: char, index: &mut usize) {
+fn consume_char(chars: &[char], expected: char, index: &mut usize) -> Result<(), String> {
+    let input = peek(chars, *index)?;
     if input != expected {
-        panic!("Expected {}. Got {}", expected, input);
+        return Err(format!("Expected {}. Got {}", expected, input));
     }
 
     *index += 1;
+    Ok(())
 }
@@ -35,7 +35,9 @@ impl TestablePattern for ExactAmountPattern {
     fn test(&self, input: &str) -> (bool, usize) {
         let mut index = 0;
         while index < self.amount {
-            let c = input.chars().nth(index).unwrap();
+            let Some(c) = input.chars().nth(index) else {
+                return (false, 0);
+            };
 
             if self.token_selector.contains(c) {
                 index += 1;
@@ -5,6 +5,7 @@ pub struct Regex {
     pub patterns: Vec<Box<dyn TestablePattern>>,
 }
 
+#[allow(dead_code)]
 pub struct Match {
     captured: String,
 }
@@ -14,6 +15,10 @@ impl Regex {
         parse_regex(pattern)
     }
 
+    pub fn try_new(pattern: &str) -> Result<Regex, String> {
+        try_parse_regex(pattern)
+    }
+
     pub fn test(&self, string: &str) -> bool {
         for s in 0..string.len() {
             if self.test_substr(&string[s..]) {
@@ -24,6 +29,26 @@ impl Regex {
         false
     }
 
+    // The length in bytes of the match at the very start of the string, if there is one
+    pub fn match_len(&self, string: &str) -> Option<usize> {
+        let mut index = 0;
+        for p in self.patterns.iter() {
+            let (matches, offset) = p.test(&string[index..]);
+            if !matches {
+                return None;
+            }
+
+            // Patterns count chars, step over them in bytes
+            index += string[index..]
+                .chars()
+                .take(offset)
+                .map(|c| c.len_utf8())
+                .sum::<usize>();
+        }
+
+        Some(index)
+    }
+
     fn test_substr(&self, string: &str) -> bool {
         let mut index = 0;
         for p in self.patterns.iter() {
@@ -39,6 +64,7 @@ impl Regex {
     }
 }
 
+#[cfg(test)]
 macro_rules! match_pattern {
     ($name:ident, $pattern:expr, $input:expr, $expected:expr) => {
         #[test]
@@ -74,4 +100,21 @@ mod test {
     match_pattern!(matches_one_of2, "[abc]", "b", true);
     match_pattern!(matches_one_of3, "[abc]", "c", true);
     match_pattern!(matches_one_of4, "[abc]", "d", false);
+    match_pattern!(matches_range1, "[0-9]+", "a7", true);
+    match_pattern!(matches_range2, "[a-c]", "d", false);
+
+    #[test]
+    fn match_len_is_anchored() {
+        let regex = Regex::new("[0-9]+");
+        assert_eq!(regex.match_len("123abc"), Some(3));
+        assert_eq!(regex.match_len("abc123"), None);
+        assert_eq!(Regex::new("ab").match_len("a"), None);
+    }
+
+    #[test]
+    fn try_new_reports_bad_patterns() {
+        assert!(Regex::try_new("[ab").is_err());
+        assert!(Regex::try_new("a{1,").is_err());
+        assert!(Regex::try_new("a{1,2}").is_ok());
+    }
 }